@@ -0,0 +1,111 @@
+;******************************************************************************
+;*                        A P _ B O O T . A S M                               *
+;*----------------------------------------------------------------------------*
+;* Startcode der Application Processors (APs). Eine AP beginnt nach dem       *
+;* Startup-IPI im Real Mode an der Adresse AP_BOOT_ADDR. Daher wird dieser    *
+;* Code von 'smp.rs' dorthin kopiert, vorher werden CR3, Stack, Einstiegs-    *
+;* funktion und CPU-Nummer in die Variablen am Ende eingetragen.              *
+;*                                                                            *
+;* Der Code schaltet wie 'boot.asm' ueber den Protected Mode in den Long Mode *
+;* und nutzt die Seitentabelle des Bootstrap Processors. Die GDT hat dasselbe *
+;* Layout wie in 'boot.asm', sodass die Selektoren gleich bleiben.            *
+;******************************************************************************
+
+; Zieladresse, muss mit 'AP_BOOT_ADDR' in 'smp.rs' uebereinstimmen
+AP_BOOT_ADDR: equ 0x8000
+
+; Adresse eines Labels nach dem Kopieren
+%define REL(label) (label - _ap_boot_start + AP_BOOT_ADDR)
+
+[GLOBAL _ap_boot_start]
+[GLOBAL _ap_boot_end]
+[GLOBAL _ap_boot_cr3]
+[GLOBAL _ap_boot_stack]
+[GLOBAL _ap_boot_entry]
+[GLOBAL _ap_boot_cpu]
+
+[SECTION .text]
+
+[BITS 16]
+_ap_boot_start:
+   cli
+   cld
+   xor    ax, ax
+   mov    ds, ax
+   lgdt   [REL(_ap_gdt_80)]
+
+   ; Protected Mode einschalten
+   mov    eax, cr0
+   or     eax, 1
+   mov    cr0, eax
+   jmp    dword 1 * 0x8 : REL(_ap_boot32)    ; 32-Bit-Codesegment
+
+[BITS 32]
+_ap_boot32:
+   mov    ax, 3 * 0x8                        ; Datensegment
+   mov    ds, ax
+   mov    es, ax
+   mov    ss, ax
+
+   ; PAE aktivieren
+   mov    eax, cr4
+   or     eax, 1 << 5
+   mov    cr4, eax
+
+   ; Seitentabelle des Bootstrap Processors
+   mov    eax, [REL(_ap_boot_cr3)]
+   mov    cr3, eax
+
+   ; Long-Mode aktivieren
+   mov    ecx, 0x0C0000080                   ; EFER
+   rdmsr
+   or     eax, 1 << 8                        ; LME
+   wrmsr
+
+   ; Paging aktivieren
+   mov    eax, cr0
+   or     eax, 1 << 31
+   mov    cr0, eax
+
+   jmp    2 * 0x8 : REL(_ap_boot64)          ; 64-Bit-Codesegment
+
+[BITS 64]
+_ap_boot64:
+   mov    ax, 3 * 0x8
+   mov    ds, ax
+   mov    es, ax
+   mov    fs, ax
+   mov    gs, ax
+   mov    ss, ax
+
+   ; Stack setzen und Einstiegsfunktion mit der CPU-Nummer aufrufen
+   mov    rsp, [REL(_ap_boot_stack)]
+   mov    rdi, [REL(_ap_boot_cpu)]
+   mov    rax, [REL(_ap_boot_entry)]
+   call   rax
+
+   cli                                       ; Hier sollten wir nicht hinkommen
+   hlt
+
+align 8
+_ap_gdt:
+   dq 0                                      ; NULL-Deskriptor
+   dq 0x00CF9A000000FFFF                     ; 32-Bit-Codesegment
+   dq 0x00AF9A000000FFFF                     ; 64-Bit-Codesegment
+   dq 0x00CF92000000FFFF                     ; Datensegment
+
+_ap_gdt_80:
+   dw  4*8 - 1
+   dd  REL(_ap_gdt)
+
+; Werden von 'smp.rs' vor dem Start einer AP gesetzt
+align 8
+_ap_boot_cr3:
+   dq 0
+_ap_boot_stack:
+   dq 0
+_ap_boot_entry:
+   dq 0
+_ap_boot_cpu:
+   dq 0
+_ap_boot_end:
@@ -53,6 +53,9 @@ _pagetable_end:  equ 0x200000      ;  = 2 MB
 ; Kernel-Stack im TSS setzen (beim Thread-Wechsel)
 [GLOBAL _tss_set_rsp0]
 
+; GDT, wird fuer die Application Processors kopiert (siehe 'smp.rs')
+[GLOBAL _gdt]
+
 ; Rust-Einstiegsfunktion die am Ende des Assembler-Codes aufgerufen werden
 [EXTERN kmain]
 
@@ -287,9 +290,24 @@ _tss_set_base_address:
 ;
 ; Kernel-Stack im TSS = rsp0 setzen
 ; 1. Parameter -> rdi = Zeiger auf den Stack (letzter genutzer Eintrag)
+;
+; Jede CPU hat eine eigene GDT und ein eigenes TSS. Die Adresse des TSS
+; wird daher aus dem TSS-Deskriptor (Selektor 0x30) der GDT dieser CPU gelesen.
 _tss_set_rsp0:
-   mov rax, _tss
-   mov [rax+4], rdi
+   sub rsp, 16
+   sgdt [rsp]           ; Limit (2 Bytes) und Basisadresse (8 Bytes)
+   mov rax, [rsp+2]
+   add rsp, 16
+   add rax, 0x30        ; TSS-Deskriptor
+   xor rcx, rcx
+   mov ecx, [rax+8]     ; Basis 32..63
+   shl rcx, 8
+   mov cl, [rax+7]      ; Basis 24..31
+   shl rcx, 8
+   mov cl, [rax+4]      ; Basis 16..23
+   shl rcx, 16
+   mov cx, [rax+2]      ; Basis 0..15
+   mov [rcx+4], rdi
    ret
 
 
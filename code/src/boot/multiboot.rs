@@ -119,6 +119,21 @@ impl From<u32> for MmapType {
     }
 }
 
+//
+// Kommandozeile des Kernels (in 'grub.cfg' hinter dem Kernel-Image),
+// 'None' falls der Bootloader keine uebergeben hat
+//
+pub fn get_cmdline(mbi_ptr: u64) -> Option<&'static str> {
+    let mb_info: &MultibootInfo = unsafe { MultibootInfo::read(mbi_ptr) };
+    let flags = mb_info.flags;
+    let cmdline = mb_info.cmdline;
+    if flags & 0x4 == 0 || cmdline == 0 {
+        return None;
+    }
+    let cstr = unsafe { core::ffi::CStr::from_ptr(cmdline as u64 as *const core::ffi::c_char) };
+    cstr.to_str().ok()
+}
+
 //
 // Ermittel freie Speicherbereiche im physikalischen Adressraum
 //
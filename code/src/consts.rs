@@ -4,5 +4,22 @@
 pub const STACK_SIZE: usize = 0x80000;
 pub const STACK_ALIGNMENT: usize = 8;
 pub const STACK_ENTRY_SIZE: usize = 8;
+// Smallest stack size accepted by 'thread::Builder'
+pub const MIN_STACK_SIZE: usize = 0x1000;
 pub const HEAP_SIZE: usize  = 16 * 1024 * 1024;    // 16 MB heap size
 
+
+// Thread priorities: 0 is the lowest, PRIORITY_LEVELS - 1 the highest level
+pub const PRIORITY_LEVELS: usize = 4;
+pub const DEFAULT_PRIORITY: usize = 1;
+// A ready thread waiting this many ticks (10ms each) is promoted one level
+pub const AGING_TICKS: u64 = 20;
+
+// Real-time threads may use at most this share of the cpu (percent), the rest is left for normal threads
+pub const RT_MAX_UTILIZATION: u64 = 90;
+
+// Maximum number of cpus (size of the per-cpu tables, at most 64 for the affinity masks)
+pub const MAX_CPUS: usize = 8;
+
+// Lock-order validator (see 'kernel::lockdep'), costs time on every lock operation
+pub const LOCKDEP: bool = false;
@@ -9,7 +9,7 @@
 */
 
 use alloc::boxed::Box;
-use spin::Mutex;
+use crate::kernel::lockdep::SpinMutex;
 
 
 use crate::kernel::cpu::{self as cpu, inb};
@@ -17,6 +17,7 @@ use crate::devices::key as key;  // shortcut for key
 use crate::cpu::outb;
 use core::sync::atomic::{AtomicU8,Ordering};
 use crate::kernel::interrupts::{intdispatcher, isr, pic as pic};
+use crate::kernel::executor;
 
 use super::cga;
 use super::key::Key;
@@ -38,7 +39,7 @@ pub fn get_lastkey() -> u8 {
 static LAST_KEY: AtomicU8 = AtomicU8::new(0);
 
 // Global thread-safe access to keyboard
-static KB: Mutex<Keyboard> = Mutex::new( 
+static KB: SpinMutex<Keyboard> = SpinMutex::new("KB",
                         Keyboard{code:0, 
 							     prefix:0, 
 							     gather: key::Key{asc:0, scan:0, modi:0}, 
@@ -135,6 +136,7 @@ const KBD_REPLY_ACK:u8 = 0xfa;
       let mut key = kb.key_hit_irq();
       if key.valid() {
          LAST_KEY.store(key.asc, Ordering::SeqCst);
+         executor::keyboard::key_pressed(key);
       }
      }
 }
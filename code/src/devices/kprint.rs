@@ -1,12 +1,12 @@
 use core::fmt;
 use core::fmt::Write;
-use spin::Mutex;
+use crate::kernel::lockdep::SpinMutex;
 use crate::devices::serial;
 
 
 // The global writer that can used as an interface from other modules
-// It is threadsafe by using 'Mutex'
-pub static WRITER: Mutex<Writer> = Mutex::new( Writer{} );
+// It is threadsafe by using 'SpinMutex'
+pub static WRITER: SpinMutex<Writer> = SpinMutex::new("kprint::WRITER", Writer{} );
 
 // Defining a Writer for writing formatted strings to the CGA screen
 pub struct Writer { }
@@ -0,0 +1,241 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: lapic                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Local APIC of each cpu. Used to start the application           ║
+   ║         processors (INIT-SIPI-SIPI) and as timer of the application     ║
+   ║         processors. The bootstrap processor keeps the PIT, its device   ║
+   ║         interrupts still come from the PIC via LINT0.                   ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::devices::pit;
+use crate::kernel::cpu;
+use crate::kernel::interrupts::intdispatcher;
+use crate::kernel::interrupts::isr;
+use crate::kernel::threads::cpu_load;
+use crate::kernel::threads::scheduler::SCHEDULER;
+use crate::kernel::threads::thread;
+
+// Register-Offsets
+const REG_ID: usize = 0x20;
+const REG_TPR: usize = 0x80;
+const REG_EOI: usize = 0xb0;
+const REG_SVR: usize = 0xf0;
+const REG_ICR_LOW: usize = 0x300;
+const REG_ICR_HIGH: usize = 0x310;
+const REG_LVT_TIMER: usize = 0x320;
+const REG_LVT_LINT0: usize = 0x350;
+const REG_LVT_LINT1: usize = 0x360;
+const REG_TIMER_INIT: usize = 0x380;
+const REG_TIMER_CURRENT: usize = 0x390;
+const REG_TIMER_DIVIDE: usize = 0x3e0;
+
+// MSR mit der Basisadresse, Bit 11 schaltet den APIC global ein
+const MSR_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// Bits in den Registern
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_EXTINT: u32 = 0b111 << 8;
+const LVT_NMI: u32 = 0b100 << 8;
+const TIMER_PERIODIC: u32 = 1 << 17;
+const TIMER_DIVIDE_16: u32 = 0b0011;
+const ICR_INIT: u32 = 0b101 << 8;
+const ICR_STARTUP: u32 = 0b110 << 8;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+const ICR_PENDING: u32 = 1 << 12;
+
+// Interrupt-Vektoren
+pub const INT_VEC_LAPIC_TIMER: usize = 0x30;
+pub const INT_VEC_SPURIOUS: usize = 0xff;
+
+// Adresse der Register, 0 solange der APIC nicht initialisiert ist
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+// Timer-Zaehlerwert fuer einen PIT-Tick, siehe 'calibrate'
+static TICKS_PER_PIT_TICK: AtomicU32 = AtomicU32::new(0);
+
+// Seitentabelle mit 2 MB Seiten aus 'boot.asm'
+extern "C" {
+    static mut _pd: u64;
+}
+
+fn read(reg: usize) -> u32 {
+    let base = BASE.load(Ordering::Relaxed) as usize;
+    unsafe { ptr::read_volatile((base + reg) as *const u32) }
+}
+
+fn write(reg: usize, value: u32) {
+    let base = BASE.load(Ordering::Relaxed) as usize;
+    unsafe { ptr::write_volatile((base + reg) as *mut u32, value) }
+}
+
+/**
+ Description: Check if `init_bsp` has been called
+*/
+pub fn is_initialized() -> bool {
+    BASE.load(Ordering::Relaxed) != 0
+}
+
+/**
+ Description: APIC-ID of the calling cpu
+*/
+pub fn id() -> u32 {
+    read(REG_ID) >> 24
+}
+
+/**
+ Description: Signal end of interrupt, needed for all interrupts
+              delivered by the local APIC (not for the PIC)
+*/
+pub fn eoi() {
+    write(REG_EOI, 0);
+}
+
+// Local APIC dieser CPU einschalten, alle Interrupt-Prioritaeten zulassen
+fn enable() {
+    cpu::wrmsr(MSR_APIC_BASE, cpu::rdmsr(MSR_APIC_BASE) | APIC_BASE_ENABLE);
+    write(REG_SVR, SVR_ENABLE | INT_VEC_SPURIOUS as u32);
+    write(REG_TPR, 0);
+}
+
+/**
+ Description: Initialize the local APIC of the bootstrap processor. The
+              registers are mapped uncached. The PIC stays connected through
+              LINT0 (virtual wire mode). Interrupts are enabled shortly to
+              calibrate the timer against the PIT.
+
+ Parameters: \
+    `address` physical address of the registers, see `acpi::Madt`
+*/
+pub fn init_bsp(address: u64) {
+    // MMIO darf nicht gecacht werden (PCD und PWT in der 2 MB Seite setzen)
+    unsafe {
+        let pd = ptr::addr_of_mut!(_pd);
+        *pd.add((address >> 21) as usize) |= 0x18;
+    }
+    cpu::invlpg(address);
+    BASE.store(address, Ordering::SeqCst);
+
+    enable();
+    write(REG_LVT_LINT0, LVT_EXTINT);
+    write(REG_LVT_LINT1, LVT_NMI);
+
+    intdispatcher::register(INT_VEC_LAPIC_TIMER, Box::new(LapicTimerISR {}));
+    intdispatcher::register(INT_VEC_SPURIOUS, Box::new(SpuriousISR {}));
+
+    calibrate();
+    kprintln!(
+        "lapic: id = {}, timer ticks per {}ms = {}",
+        id(),
+        pit::TICK_MS,
+        TICKS_PER_PIT_TICK.load(Ordering::SeqCst)
+    );
+}
+
+/**
+ Description: Initialize the local APIC of an application processor and
+              start its periodic timer (same period as the PIT).
+*/
+pub fn init_ap() {
+    enable();
+    write(REG_LVT_LINT0, LVT_MASKED);
+    write(REG_LVT_LINT1, LVT_MASKED);
+
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    write(REG_LVT_TIMER, TIMER_PERIODIC | INT_VEC_LAPIC_TIMER as u32);
+    write(REG_TIMER_INIT, TICKS_PER_PIT_TICK.load(Ordering::SeqCst));
+}
+
+// Timer-Frequenz messen: wie weit zaehlt der Timer waehrend eines PIT-Ticks?
+fn calibrate() {
+    let was_enabled = cpu::is_int_enabled();
+    cpu::enable_int();
+
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    write(REG_LVT_TIMER, LVT_MASKED);
+
+    // auf den Beginn eines Ticks warten
+    let start = pit::get_systime();
+    while pit::get_systime() == start {}
+
+    write(REG_TIMER_INIT, u32::MAX);
+    let start = pit::get_systime();
+    while pit::get_systime() == start {}
+    let elapsed = u32::MAX - read(REG_TIMER_CURRENT);
+    write(REG_TIMER_INIT, 0);
+
+    TICKS_PER_PIT_TICK.store(elapsed, Ordering::SeqCst);
+    if was_enabled == false {
+        cpu::disable_int();
+    }
+}
+
+// Interprozessor-Interrupt senden und warten, bis er zugestellt ist
+fn send_ipi(apic_id: u32, command: u32) {
+    write(REG_ICR_HIGH, apic_id << 24);
+    write(REG_ICR_LOW, command);
+    while read(REG_ICR_LOW) & ICR_PENDING != 0 {
+        cpu::pause();
+    }
+}
+
+/**
+ Description: Send an INIT IPI to the cpu `apic_id`
+*/
+pub fn send_init(apic_id: u32) {
+    send_ipi(apic_id, ICR_INIT | ICR_LEVEL_ASSERT);
+}
+
+/**
+ Description: Send a startup IPI to the cpu `apic_id`. It starts in real mode
+              at address `page * 4096`.
+*/
+pub fn send_startup(apic_id: u32, page: u8) {
+    send_ipi(apic_id, ICR_STARTUP | ICR_LEVEL_ASSERT | page as u32);
+}
+
+struct LapicTimerISR;
+
+impl isr::ISR for LapicTimerISR {
+    /**
+     Description: ISR of the local APIC timer of an application processor.
+                  The system time is counted by the PIT on the bootstrap
+                  processor. Like the PIT ISR we wake up sleeping threads and
+                  release real-time jobs, so they do not depend on the
+                  bootstrap processor getting the scheduler lock.
+    */
+    fn trigger(&self) {
+        eoi();
+
+        let opt = SCHEDULER.try_lock();
+        let (cur, next);
+        if let Some(mut s) = opt {
+            cpu_load::tick(s.is_idle_active());
+            s.account_tick();
+            s.wakeup_deferred();
+            s.wakeup_sleepers();
+            s.release_realtime();
+            (cur, next) = s.prepare_preempt();
+            if cur.is_null() || next.is_null() || cur == next {
+                return;
+            }
+        } else {
+            // the scheduler is locked, so the idle thread is not running
+            cpu_load::tick(false);
+            return;
+        }
+        thread::Thread::switch(cur, next);
+    }
+}
+
+struct SpuriousISR;
+
+impl isr::ISR for SpuriousISR {
+    // Kein EOI bei Spurious Interrupts
+    fn trigger(&self) {}
+}
@@ -12,3 +12,5 @@ pub mod fonts;
 
 #[macro_use]
 pub mod kprint;
+
+pub mod lapic;
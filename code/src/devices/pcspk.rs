@@ -12,6 +12,7 @@
 use crate::kernel::cpu::inb;
 use crate::kernel::cpu::outb;
 use crate::devices::pit;
+use crate::kernel::threads::scheduler::Scheduler;
 
 use super::kprint;
 
@@ -124,9 +125,7 @@ fn read_counter() -> u32 {
 /**
  Description: Delay execution for given time in ms. \
               Minimum delay is 10ms. \
-              For the implementation we use counter 0 of the PIT. \
-              We might have to wait for several count downs to 0\
-              because the counter has only 16 bit.
+              The calling thread sleeps, so other threads can use the cpu.
 
  Parameters: \
             `d` duration in ms
@@ -156,8 +155,8 @@ fn delay(d: u32) {
     if d < 10 {
         return;
     }
-    let start = pit::get_systime();
-    while start + (d/10) as u64 > pit::get_systime() {}
+    // Block the thread instead of busy waiting on the systime
+    Scheduler::sleep(d as u64 / pit::TICK_MS);
 }
 
 
@@ -13,9 +13,11 @@ use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use crate::devices::cga;
 use crate::kernel::cpu;
+use crate::kernel::executor::timer;
 use crate::kernel::interrupts::intdispatcher;
 use crate::kernel::interrupts::isr;
 use crate::kernel::interrupts::pic;
+use crate::kernel::threads::cpu_load;
 use crate::kernel::threads::scheduler;
 use crate::kernel::threads::scheduler::SCHEDULER;
 use crate::kernel::threads::thread;
@@ -32,6 +34,9 @@ pub fn get_systime() -> u64 {
 const PORT_CTRL: u16 = 0x43;
 const PORT_DATA0: u16 = 0x40;
 
+// duration of one tick in ms, see 'plugin'
+pub const TICK_MS: u64 = 10;
+
 // system time ticks (each 10ms one incremented)
 static SYS_TIME: AtomicU64 = AtomicU64::new(0);
 
@@ -74,7 +79,7 @@ pub fn plugin() {
     pic::allow(pic::IRQ_TIMER);
 
     // Start the pit
-    interval(10);
+    interval(TICK_MS as u32);
 }
 
 struct PitISR;
@@ -88,6 +93,9 @@ impl isr::ISR for PitISR {
         // progress system time by one tick
         let time = SYS_TIME.fetch_add(1, Ordering::SeqCst);
 
+        // wake up async tasks waiting in 'executor::timer::sleep'
+        timer::tick(time + 1);
+
         /* Hier muss Code eingefuegt werden */
 
         // Rotate the spinner each 100 ticks. One tick is 10ms, so the spinner
@@ -103,16 +111,23 @@ impl isr::ISR for PitISR {
         }
         /* Hier muss Code eingefuegt werden */
 
-        // We try to switch to the next thread 
+        // We wake up sleeping threads which are due and try to switch to the next thread 
         let opt = scheduler::SCHEDULER.try_lock();
         let (cur, next);
         if let Some(mut s) = opt {
+            cpu_load::tick(s.is_idle_active());
+            s.account_tick();
+            s.wakeup_deferred();
+            s.wakeup_sleepers();
+            s.release_realtime();
             (cur, next) = s.prepare_preempt();
             if cur.is_null() || next.is_null() || cur == next {
                 return;
 
             }
         } else {
+            // the scheduler is locked, so the idle thread is not running
+            cpu_load::tick(false);
             return;
         }
         thread::Thread::switch(cur, next);       
@@ -0,0 +1,199 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: acpi                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Minimal ACPI support. Searches the RSDP in the BIOS area and    ║
+   ║         reads the MADT (signature "APIC") to find the local APICs of    ║
+   ║         all processors and the I/O APICs.                               ║
+   ║                                                                         ║
+   ║         More information about the MADT can be found here:              ║
+   ║            https://wiki.osdev.org/MADT                                  ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr;
+use core::slice;
+
+// Root System Description Pointer (ACPI 1.0 Teil, ab Revision 2 mit XSDT)
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // ab Revision 2
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+// Gemeinsamer Kopf aller System Description Tables
+#[repr(C, packed)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+// Eintragstypen in der MADT
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_LOCAL_APIC_OVERRIDE: u8 = 5;
+
+// Flags eines Local-APIC-Eintrags
+const LAPIC_ENABLED: u32 = 1 << 0;
+const LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/**
+ Description: I/O APIC found in the MADT
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u64,
+    pub gsi_base: u32, // erste globale Interruptnummer dieses I/O APICs
+}
+
+/**
+ Description: Information taken from the MADT
+*/
+pub struct Madt {
+    pub lapic_address: u64, // physikalische Adresse der Local APICs (fuer alle CPUs gleich)
+    pub apic_ids: Vec<u8>,  // APIC-IDs der nutzbaren Prozessoren
+    pub io_apics: Vec<IoApic>,
+}
+
+// Summe aller Bytes muss 0 sein
+fn checksum_ok(addr: usize, len: usize) -> bool {
+    let bytes = unsafe { slice::from_raw_parts(addr as *const u8, len) };
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+// RSDP in einem Speicherbereich suchen, liegt immer an einer 16-Byte-Grenze
+fn search_rsdp(start: usize, end: usize) -> Option<usize> {
+    let mut addr = start;
+    while addr + 20 <= end {
+        let signature = unsafe { slice::from_raw_parts(addr as *const u8, 8) };
+        if signature == b"RSD PTR " && checksum_ok(addr, 20) {
+            return Some(addr);
+        }
+        addr += 16;
+    }
+    None
+}
+
+// Zuerst das erste KB der EBDA durchsuchen, dann das BIOS-ROM
+fn find_rsdp() -> Option<usize> {
+    let ebda = unsafe { ptr::read_volatile(0x40e as *const u16) } as usize * 16;
+    if ebda != 0 {
+        if let Some(addr) = search_rsdp(ebda, ebda + 1024) {
+            return Some(addr);
+        }
+    }
+    search_rsdp(0xe0000, 0x100000)
+}
+
+// Tabelle mit 'signature' ueber RSDT oder XSDT finden
+fn find_table(signature: &[u8; 4]) -> Option<usize> {
+    let rsdp_addr = find_rsdp()?;
+    let rsdp = unsafe { &*(rsdp_addr as *const Rsdp) };
+
+    // XSDT hat 64-Bit-Eintraege, RSDT 32-Bit-Eintraege
+    let xsdt = rsdp.xsdt_address;
+    let (root, entry_size) = if rsdp.revision >= 2 && xsdt != 0 {
+        (xsdt as usize, 8)
+    } else {
+        (rsdp.rsdt_address as usize, 4)
+    };
+
+    let header = unsafe { &*(root as *const SdtHeader) };
+    let len = header.length as usize;
+    if checksum_ok(root, len) == false {
+        kprintln!("acpi: invalid checksum of root table");
+        return None;
+    }
+
+    let entries = (len - size_of::<SdtHeader>()) / entry_size;
+    for i in 0..entries {
+        let entry = root + size_of::<SdtHeader>() + i * entry_size;
+        let table = unsafe {
+            if entry_size == 8 {
+                ptr::read_unaligned(entry as *const u64) as usize
+            } else {
+                ptr::read_unaligned(entry as *const u32) as usize
+            }
+        };
+        let header = unsafe { &*(table as *const SdtHeader) };
+        if &header.signature == signature && checksum_ok(table, header.length as usize) {
+            return Some(table);
+        }
+    }
+    None
+}
+
+/**
+ Description: Read the MADT. Only enabled processors (or processors which
+              can be enabled) are returned.
+
+ Return: \
+    `None` if there is no ACPI or no MADT, e.g. on very old systems
+*/
+pub fn read_madt() -> Option<Madt> {
+    let table = find_table(b"APIC")?;
+    let header = unsafe { &*(table as *const SdtHeader) };
+    let end = table + header.length as usize;
+
+    // Nach dem Kopf folgen die Adresse der Local APICs und Flags
+    let mut madt = Madt {
+        lapic_address: unsafe { ptr::read_unaligned((table + size_of::<SdtHeader>()) as *const u32) } as u64,
+        apic_ids: Vec::new(),
+        io_apics: Vec::new(),
+    };
+
+    // Danach Eintraege mit variabler Laenge: Typ (1 Byte), Laenge (1 Byte), Daten
+    let mut entry = table + size_of::<SdtHeader>() + 8;
+    while entry + 2 <= end {
+        let kind = unsafe { *(entry as *const u8) };
+        let len = unsafe { *((entry + 1) as *const u8) } as usize;
+        if len < 2 {
+            break;
+        }
+        match kind {
+            MADT_LOCAL_APIC => {
+                let apic_id = unsafe { *((entry + 3) as *const u8) };
+                let flags = unsafe { ptr::read_unaligned((entry + 4) as *const u32) };
+                if flags & (LAPIC_ENABLED | LAPIC_ONLINE_CAPABLE) != 0 {
+                    madt.apic_ids.push(apic_id);
+                }
+            }
+            MADT_IO_APIC => unsafe {
+                madt.io_apics.push(IoApic {
+                    id: *((entry + 2) as *const u8),
+                    address: ptr::read_unaligned((entry + 4) as *const u32) as u64,
+                    gsi_base: ptr::read_unaligned((entry + 8) as *const u32),
+                });
+            },
+            MADT_LOCAL_APIC_OVERRIDE => {
+                madt.lapic_address = unsafe { ptr::read_unaligned((entry + 4) as *const u64) };
+            }
+            _ => {}
+        }
+        entry += len;
+    }
+
+    kprintln!(
+        "acpi: MADT lapic_address = 0x{:x}, apic_ids = {:?}, io_apics = {:?}",
+        madt.lapic_address,
+        madt.apic_ids,
+        madt.io_apics
+    );
+    Some(madt)
+}
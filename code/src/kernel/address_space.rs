@@ -0,0 +1,209 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: address_space                                                   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Address spaces of user threads, each user thread gets its own   ║
+   ║         one unless it is given one (see 'thread::Builder'). Each        ║
+   ║         address space has its own PML4. The kernel mapping is shared    ║
+   ║         but only accessible in ring 0 (no 'PAGE_USER' bit).             ║
+   ║         'allow_user' makes single pages accessible in ring 3, e.g. the  ║
+   ║         code of the kernel image. For this the page tables on the path  ║
+   ║         to the page are copied, so later changes of the kernel in these ║
+   ║         tables are not seen by the address space. The same holds for    ║
+   ║         PML4 entries added after 'AddressSpace::new'. The region        ║
+   ║         'stack::PRIVATE_STACKS' has its own tables for the user stacks  ║
+   ║         of the threads in the address space.                            ║
+   ║                                                                         ║
+   ║         The address space is switched in 'Thread::switch' (CR3).        ║
+   ║         TLB entries are only invalidated on the calling cpu.            ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr;
+
+use crate::kernel::cpu;
+use crate::kernel::frames;
+use crate::kernel::paging::{self, ADDR_MASK, PAGE_HUGE, PAGE_NO_EXECUTE, PAGE_PRESENT};
+use crate::kernel::paging::{PAGE_SIZE, PAGE_USER, PAGE_WRITABLE};
+use crate::kernel::threads::stack;
+use crate::mylib::spinlock::Spinlock;
+
+// Code und konstante Daten des Kernel-Images, im Linker-Skript
+extern "C" {
+    static ___TEXT_START__: u8;
+    static ___TEXT_END__: u8;
+}
+
+/**
+ Description: Address space of user threads, see `thread::Builder::kernel_thread`
+*/
+pub struct AddressSpace {
+    pml4: u64,
+    tables: Spinlock<Vec<u64>>, // eigene Seitentabellen unterhalb der PML4, siehe 'allow_page'
+}
+
+// Nur ueber Spinlock veraenderte Seitentabellen
+unsafe impl Send for AddressSpace {}
+unsafe impl Sync for AddressSpace {}
+
+impl AddressSpace {
+    /**
+     Description: Create an address space with the code of the kernel image
+                  (read-only) as the only pages accessible in ring 3.
+    */
+    pub fn new() -> Arc<AddressSpace> {
+        // Alle Eintraege der Kernel-PML4 uebernehmen, aber nur fuer Ring 0.
+        // Die neue PML4 gehoert nur uns, sie ist noch in keinem CR3.
+        let pml4 = unsafe { paging::copy_table(paging::kernel_pml4(), PAGE_USER) }
+            .expect("AddressSpace::new: no frame for the PML4");
+        let stacks = paging::new_table().expect("AddressSpace::new: no frame for the PDPT of the user stacks");
+        unsafe {
+            paging::table(pml4).entries[paging::index(stack::PRIVATE_STACKS, 4)] =
+                stacks | PAGE_PRESENT | PAGE_WRITABLE | PAGE_USER;
+        }
+        let space = Arc::new(AddressSpace {
+            pml4,
+            tables: Spinlock::new(Vec::new()),
+        });
+
+        let start = ptr::addr_of!(___TEXT_START__) as u64;
+        let end = ptr::addr_of!(___TEXT_END__) as u64;
+        if space.allow_user(start, end - start, 0) == false {
+            panic!("AddressSpace::new: cannot map the code of the kernel image");
+        }
+        space
+    }
+
+    /**
+     Description: Physical address of the PML4, for CR3
+    */
+    pub fn get_pml4(&self) -> u64 {
+        self.pml4
+    }
+
+    /**
+     Description: Make the pages of `[start; start + len)` accessible in
+                  ring 3. The pages must be mapped in the kernel.
+
+     Parameters: \
+        `flags` `PAGE_WRITABLE` and/or `PAGE_NO_EXECUTE`, other bits are ignored
+
+     Return: \
+        `false` if a page is not mapped or there was no frame for a page table
+    */
+    pub fn allow_user(&self, start: u64, len: u64, flags: u64) -> bool {
+        let mut flags = flags & (PAGE_WRITABLE | PAGE_NO_EXECUTE);
+        if paging::nx_enabled() == false {
+            flags &= !PAGE_NO_EXECUTE;
+        }
+
+        let was_enabled = cpu::disable_int_nested();
+        let mut tables = self.tables.lock();
+        let mut ok = true;
+        let mut page = start & !(PAGE_SIZE - 1);
+        while page < start + len {
+            if self.allow_page(&mut tables, page, flags) == false {
+                ok = false;
+                break;
+            }
+            page += PAGE_SIZE;
+        }
+        drop(tables);
+        cpu::enable_int_nested(was_enabled);
+        ok
+    }
+
+    /**
+     Description: Make the pages of `[start; start + len)` inaccessible in
+                  ring 3 again, e.g. before the memory is freed.
+    */
+    pub fn deny_user(&self, start: u64, len: u64) {
+        let was_enabled = cpu::disable_int_nested();
+        let tables = self.tables.lock();
+        let mut page = start & !(PAGE_SIZE - 1);
+        while page < start + len {
+            if let Some(entry) = self.user_entry(&tables, page) {
+                *entry &= !PAGE_USER;
+                cpu::invlpg(page);
+            }
+            page += PAGE_SIZE;
+        }
+        drop(tables);
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    //
+    // Eine Seite im Ring 3 zugreifbar machen. Gemeinsame Tabellen des Kernels
+    // auf dem Weg dorthin werden vorher ohne 'PAGE_USER' kopiert, grosse Seiten
+    // aufgeteilt. Die PML4 und alle Tabellen in 'tables' gehoeren nur uns
+    // und werden nur mit gesperrtem 'tables' veraendert.
+    //
+    fn allow_page(&self, tables: &mut Vec<u64>, virt: u64, flags: u64) -> bool {
+        let mut t = self.pml4;
+        for level in (2..=4).rev() {
+            let entry = unsafe { &mut paging::table(t).entries[paging::index(virt, level)] };
+            if *entry & PAGE_PRESENT == 0 {
+                return false;
+            }
+            if *entry & PAGE_HUGE != 0 {
+                if unsafe { paging::split(entry, level) } == false {
+                    return false;
+                }
+                tables.push(*entry & ADDR_MASK);
+            } else if tables.contains(&(*entry & ADDR_MASK)) == false {
+                let copy = match unsafe { paging::copy_table(*entry & ADDR_MASK, PAGE_USER) } {
+                    Some(c) => c,
+                    None => return false,
+                };
+                tables.push(copy);
+                *entry = copy | (*entry & !ADDR_MASK);
+            }
+            *entry |= PAGE_USER;
+            t = *entry & ADDR_MASK;
+        }
+
+        let entry = unsafe { &mut paging::table(t).entries[paging::index(virt, 1)] };
+        if *entry & PAGE_PRESENT == 0 {
+            return false;
+        }
+        *entry = (*entry & ADDR_MASK) | PAGE_PRESENT | PAGE_USER | flags;
+        true
+    }
+
+    // Eintrag der Seite 'virt' in einer eigenen Seitentabelle, sonst ist sie nicht im Ring 3 zugreifbar
+    fn user_entry(&self, tables: &Vec<u64>, virt: u64) -> Option<&'static mut u64> {
+        let mut t = self.pml4;
+        for level in (2..=4).rev() {
+            let entry = unsafe { paging::table(t).entries[paging::index(virt, level)] };
+            if entry & PAGE_PRESENT == 0 || tables.contains(&(entry & ADDR_MASK)) == false {
+                return None;
+            }
+            t = entry & ADDR_MASK;
+        }
+        Some(unsafe { &mut paging::table(t).entries[paging::index(virt, 1)] })
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        for t in self.tables.lock().drain(..) {
+            frames::free_frame(t);
+        }
+        // Tabellen der User-Stacks, die Stacks selbst sind schon freigegeben
+        let stacks = unsafe { paging::table(self.pml4).entries[paging::index(stack::PRIVATE_STACKS, 4)] };
+        free_tables(stacks & ADDR_MASK, 3);
+        frames::free_frame(self.pml4);
+    }
+}
+
+// Tabelle 'addr' der Stufe 'level' und alle Tabellen darunter freigeben
+fn free_tables(addr: u64, level: usize) {
+    if level > 1 {
+        for entry in unsafe { paging::table(addr).entries.iter() } {
+            if *entry & PAGE_PRESENT != 0 && *entry & PAGE_HUGE == 0 {
+                free_tables(*entry & ADDR_MASK, level - 1);
+            }
+        }
+    }
+    frames::free_frame(addr);
+}
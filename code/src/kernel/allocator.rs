@@ -20,6 +20,7 @@ use alloc::alloc::Layout;
 
 use crate::consts;
 use crate::kernel::allocator::list::LinkedListAllocator;
+use crate::kernel::lockdep::{SpinMutex, SpinMutexGuard};
 
 pub mod list;
 
@@ -65,22 +66,22 @@ pub fn dump_free_list() {
 }
 
 /**
- Description: A wrapper around spin::Mutex to permit trait implementations
+ Description: A wrapper around a lockdep::SpinMutex to permit trait implementations
               Required for implementing `GlobalAlloc` in `bump.rs` and 
              `list.rs`. Can be used for debugging the heap allocator. 
 */
 pub struct Locked<A> {
-    inner: spin::Mutex<A>,
+    inner: SpinMutex<A>,
 }
 
 impl<A> Locked<A> {
     pub const fn new(inner: A) -> Self {
         Locked {
-            inner: spin::Mutex::new(inner),
+            inner: SpinMutex::new("ALLOCATOR", inner),
         }
     }
 
-    pub fn lock(&self) -> spin::MutexGuard<A> {
+    pub fn lock(&self) -> SpinMutexGuard<'_, A> {
         self.inner.lock()
     }
 }
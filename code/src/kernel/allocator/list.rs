@@ -225,11 +225,18 @@ impl LinkedListAllocator {
 // Trait required by the Rust runtime for heap allocations
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
 	
+    // Die Interrupts werden gesperrt, damit auch ISRs den Heap nutzen koennen
+    // (sonst Deadlock, falls der unterbrochene Thread die Sperre haelt)
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.lock().alloc(layout)
+        let was_enabled = cpu::disable_int_nested();
+        let ptr = self.lock().alloc(layout);
+        cpu::enable_int_nested(was_enabled);
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let was_enabled = cpu::disable_int_nested();
         self.lock().dealloc(ptr, layout);
+        cpu::enable_int_nested(was_enabled);
     }
 }
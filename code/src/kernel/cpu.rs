@@ -113,7 +113,7 @@ pub fn disable_int () {
  Description: stop CPU, will be waked up by next interrupt
 */
 #[inline]
-pub fn halt () {
+pub fn halt () -> ! {
    loop {
       unsafe { asm!( "hlt" ); }
    }
@@ -139,3 +139,70 @@ pub fn pause() {
         asm!("pause", options(nomem, nostack));
     }
 }
+
+/**
+ Description: Read model specific register `msr`
+*/
+#[inline]
+pub fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack));
+    }
+    ((high as u64) << 32) | (low as u64)
+}
+
+/**
+ Description: Write `value` to model specific register `msr`
+*/
+#[inline]
+pub fn wrmsr(msr: u32, value: u64) {
+    unsafe {
+        asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack));
+    }
+}
+
+/**
+ Description: Return CR3 (physical address of the PML4)
+*/
+#[inline]
+pub fn read_cr3() -> u64 {
+    let cr3: u64;
+    unsafe {
+        asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+    cr3
+}
+
+/**
+ Description: Return CR2 (linear address of the last page fault)
+*/
+#[inline]
+pub fn read_cr2() -> u64 {
+    let cr2: u64;
+    unsafe {
+        asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+    }
+    cr2
+}
+
+/**
+ Description: Invalidate the TLB entry of the page containing `addr`
+*/
+#[inline]
+pub fn invlpg(addr: u64) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags));
+    }
+}
+
+/**
+ Description: Load `pml4` (physical address) into CR3, flushes the TLB
+              except for global pages
+*/
+#[inline]
+pub fn write_cr3(pml4: u64) {
+    unsafe {
+        asm!("mov cr3, {}", in(reg) pml4, options(nostack, preserves_flags));
+    }
+}
@@ -0,0 +1,103 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: keyboard                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Future which is ready with the next keystroke. The keyboard ISR ║
+   ║         stores the keys in a small ring buffer and calls 'key_pressed', ║
+   ║         which wakes all waiting tasks. Each waiting task gets the key.  ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use crate::devices::key::Key;
+use crate::kernel::cpu;
+use crate::mylib::spinlock::Spinlock;
+
+// Anzahl der gespeicherten Tasten, aeltere gehen verloren
+const RING_SIZE: usize = 16;
+
+// Anzahl aller bisherigen Tastendruecke
+static KEY_COUNT: AtomicUsize = AtomicUsize::new(0);
+// Die letzten Tasten, Tastendruck n steht in KEY_RING[n % RING_SIZE]
+static KEY_RING: [AtomicU32; RING_SIZE] = [const { AtomicU32::new(0) }; RING_SIZE];
+// Wartende Tasks
+static WAITERS: Spinlock<Vec<Waker>> = Spinlock::new(Vec::new());
+
+fn pack(key: Key) -> u32 {
+    (key.asc as u32) | ((key.scan as u32) << 8) | ((key.modi as u32) << 16)
+}
+
+fn unpack(v: u32) -> Key {
+    Key::new(v as u8, (v >> 8) as u8, (v >> 16) as u8)
+}
+
+/**
+ Description: Future returned by `next_key`
+*/
+pub struct NextKey {
+    count: usize, // Nummer des erwarteten Tastendrucks
+}
+
+/**
+ Description: Wait asynchronously for the next keystroke after this call,
+              e.g. `let key = next_key().await`
+*/
+pub fn next_key() -> NextKey {
+    NextKey {
+        count: KEY_COUNT.load(Ordering::SeqCst),
+    }
+}
+
+impl NextKey {
+    // Den erwarteten Tastendruck holen, falls er schon da ist
+    fn get(&self) -> Option<Key> {
+        let count = KEY_COUNT.load(Ordering::SeqCst);
+        if count <= self.count {
+            return None;
+        }
+        // Wurde die Taste schon ueberschrieben, gibt es die aelteste noch vorhandene
+        let n = self.count.max(count - RING_SIZE.min(count));
+        Some(unpack(KEY_RING[n % RING_SIZE].load(Ordering::SeqCst)))
+    }
+}
+
+impl Future for NextKey {
+    type Output = Key;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Key> {
+        if let Some(key) = self.get() {
+            return Poll::Ready(key);
+        }
+
+        let was_enabled = cpu::disable_int_nested();
+        let mut waiters = WAITERS.lock();
+        if waiters.iter().any(|w| w.will_wake(cx.waker())) == false {
+            waiters.push(cx.waker().clone());
+        }
+        drop(waiters);
+        cpu::enable_int_nested(was_enabled);
+
+        // Die Taste kann zwischen der Pruefung und dem Eintragen gekommen sein
+        match self.get() {
+            Some(key) => Poll::Ready(key),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/**
+ Description: Store `key` and wake up the waiting tasks. Called by the keyboard ISR.
+*/
+pub fn key_pressed(key: Key) {
+    let count = KEY_COUNT.load(Ordering::SeqCst);
+    KEY_RING[count % RING_SIZE].store(pack(key), Ordering::SeqCst);
+    KEY_COUNT.store(count + 1, Ordering::SeqCst);
+
+    let waiters = core::mem::take(&mut *WAITERS.lock());
+    for waker in waiters {
+        waker.wake();
+    }
+}
@@ -0,0 +1,211 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: executor                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Small executor for async tasks ('Future's). All tasks of an     ║
+   ║         executor run in the kernel thread calling 'run'. A task is only ║
+   ║         polled again after its waker has been called, e.g. from an ISR  ║
+   ║         (see 'timer' and 'keyboard'). Without ready tasks the thread    ║
+   ║         blocks on a semaphore, which the wakers signal.                 ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+pub mod keyboard;
+pub mod timer;
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use crate::kernel::cpu;
+use crate::mylib::semaphore::Semaphore;
+use crate::mylib::spinlock::Spinlock;
+
+type TaskFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Von Executor, Spawnern und Wakern gemeinsam genutzt. Die Locks werden auch
+// in ISRs (Waker) benutzt, daher immer mit gesperrten Interrupts.
+struct Shared {
+    ready: Spinlock<VecDeque<usize>>,            // ids der zu pollenden Tasks
+    incoming: Spinlock<Vec<(usize, TaskFuture)>>, // mit 'Spawner' erzeugte Tasks
+    signal: Semaphore,                           // weckt den Thread in 'run'
+    next_id: AtomicUsize,
+}
+
+impl Shared {
+    fn push_ready(&self, id: usize) {
+        let was_enabled = cpu::disable_int_nested();
+        self.ready.lock().push_back(id);
+        cpu::enable_int_nested(was_enabled);
+        self.signal.v();
+    }
+
+    fn pop_ready(&self) -> Option<usize> {
+        let was_enabled = cpu::disable_int_nested();
+        let id = self.ready.lock().pop_front();
+        cpu::enable_int_nested(was_enabled);
+        id
+    }
+}
+
+// Waker eines Tasks
+struct TaskWaker {
+    id: usize,
+    queued: AtomicBool, // steht schon in 'ready', mehrfaches Wecken zaehlt nur einmal
+    shared: Arc<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.queued.swap(true, Ordering::SeqCst) == false {
+            self.shared.push_ready(self.id);
+        }
+    }
+}
+
+struct Task {
+    future: TaskFuture,
+    waker: Arc<TaskWaker>,
+}
+
+/**
+ Description: Executor running async tasks in the calling kernel thread
+*/
+pub struct Executor {
+    tasks: BTreeMap<usize, Task>,
+    shared: Arc<Shared>,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor {
+            tasks: BTreeMap::new(),
+            shared: Arc::new(Shared {
+                ready: Spinlock::new(VecDeque::new()),
+                incoming: Spinlock::new(Vec::new()),
+                signal: Semaphore::new(0),
+                next_id: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /**
+     Description: Add a task, it is polled the first time in `run`
+    */
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&mut self, future: F) {
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        self.add_task(id, Box::pin(future));
+    }
+
+    /**
+     Description: Get a handle for spawning tasks from other tasks or threads
+    */
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /**
+     Description: Number of unfinished tasks
+    */
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /**
+     Description: Check if all tasks are finished
+    */
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /**
+     Description: Run the tasks until all are finished. Blocks the calling
+                  thread while no task is ready. Must not be called from an ISR.
+    */
+    pub fn run(&mut self) {
+        loop {
+            self.add_incoming();
+            if self.tasks.is_empty() {
+                return;
+            }
+            match self.shared.pop_ready() {
+                Some(id) => self.poll_task(id),
+                None => self.shared.signal.p(),
+            }
+        }
+    }
+
+    fn add_task(&mut self, id: usize, future: TaskFuture) {
+        let waker = Arc::new(TaskWaker {
+            id,
+            queued: AtomicBool::new(true),
+            shared: self.shared.clone(),
+        });
+        self.tasks.insert(id, Task { future, waker });
+        self.shared.push_ready(id);
+    }
+
+    // Mit 'Spawner' erzeugte Tasks uebernehmen
+    fn add_incoming(&mut self) {
+        let was_enabled = cpu::disable_int_nested();
+        let incoming = core::mem::take(&mut *self.shared.incoming.lock());
+        cpu::enable_int_nested(was_enabled);
+        for (id, future) in incoming {
+            self.add_task(id, future);
+        }
+    }
+
+    fn poll_task(&mut self, id: usize) {
+        // Ein Waker kann auf einen schon beendeten Task zeigen
+        let task = match self.tasks.get_mut(&id) {
+            Some(t) => t,
+            None => return,
+        };
+        // Vor dem Pollen zuruecksetzen, damit ein Wecken waehrend 'poll' nicht verloren geht
+        task.waker.queued.store(false, Ordering::SeqCst);
+        let waker = Waker::from(task.waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        if let Poll::Ready(()) = task.future.as_mut().poll(&mut cx) {
+            self.tasks.remove(&id);
+        }
+    }
+}
+
+/**
+ Description: Handle for spawning tasks in an `Executor`, may be cloned
+              and sent to other threads.
+*/
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+impl Spawner {
+    /**
+     Description: Add a task to the executor, it is polled the next time
+                  the executor checks for new tasks.
+    */
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&self, future: F) {
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        let was_enabled = cpu::disable_int_nested();
+        self.shared.incoming.lock().push((id, Box::pin(future)));
+        cpu::enable_int_nested(was_enabled);
+        self.shared.signal.v();
+    }
+}
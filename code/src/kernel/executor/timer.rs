@@ -0,0 +1,90 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: timer                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Future which is ready after a number of PIT ticks. The wakers   ║
+   ║         of the waiting tasks are called by 'tick' from the PIT ISR.     ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use crate::devices::pit;
+use crate::kernel::cpu;
+use crate::mylib::spinlock::Spinlock;
+
+// Wartende Tasks mit ihrer Weckzeit
+static TIMERS: Spinlock<Vec<(u64, Waker)>> = Spinlock::new(Vec::new());
+// Frueheste Weckzeit in 'TIMERS', damit 'tick' meist sofort fertig ist
+static NEXT_WAKEUP: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/**
+ Description: Future returned by `sleep`
+*/
+pub struct Sleep {
+    wakeup: u64, // Systemzeit in PIT-Ticks
+}
+
+/**
+ Description: Wait asynchronously for `ticks` PIT ticks, e.g. `sleep(100).await`
+*/
+pub fn sleep(ticks: u64) -> Sleep {
+    Sleep {
+        wakeup: pit::get_systime() + ticks,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if pit::get_systime() >= self.wakeup {
+            return Poll::Ready(());
+        }
+
+        let was_enabled = cpu::disable_int_nested();
+        let mut timers = TIMERS.lock();
+        let known = timers
+            .iter()
+            .any(|(t, w)| *t == self.wakeup && w.will_wake(cx.waker()));
+        if known == false {
+            timers.push((self.wakeup, cx.waker().clone()));
+            NEXT_WAKEUP.fetch_min(self.wakeup, Ordering::SeqCst);
+        }
+        drop(timers);
+        cpu::enable_int_nested(was_enabled);
+
+        // Der Tick kann zwischen der Pruefung und dem Eintragen gekommen sein
+        if pit::get_systime() >= self.wakeup {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/**
+ Description: Wake up the tasks whose time has come. Called by the PIT ISR.
+
+ Parameters: \
+    `now` current system time
+*/
+pub fn tick(now: u64) {
+    if now < NEXT_WAKEUP.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut timers = TIMERS.lock();
+    let mut next = u64::MAX;
+    timers.retain(|(wakeup, waker)| {
+        if *wakeup <= now {
+            waker.wake_by_ref();
+            false
+        } else {
+            next = next.min(*wakeup);
+            true
+        }
+    });
+    NEXT_WAKEUP.store(next, Ordering::SeqCst);
+}
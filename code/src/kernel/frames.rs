@@ -0,0 +1,289 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: frames                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Allocator for physical page frames of 4 KiB. The free frames    ║
+   ║         are taken from 'multiboot::get_free_memory' and managed in a    ║
+   ║         bitmap (one bit per frame, 1 = used) covering the physical      ║
+   ║         address range from the lowest to the highest free frame.        ║
+   ║         Frames are addressed by their physical address, which is        ║
+   ║         identity mapped (see '_setup_paging' in 'boot.asm').            ║
+   ║                                                                         ║
+   ║         The page fault handler allocates with 'alloc_frame_for_fault'.  ║
+   ║         If the fault occurred while the same cpu holds the lock, it     ║
+   ║         gets the reserve frame of this cpu instead of a deadlock.       ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::boot::multiboot::PhysRegion;
+use crate::consts;
+use crate::kernel::cpu;
+use crate::kernel::lockdep::SpinMutex;
+use crate::kernel::smp;
+
+pub const FRAME_SIZE: u64 = 0x1000;
+
+// Bits je Eintrag in der Bitmap
+const BITS: usize = 64;
+
+static FRAMES: SpinMutex<FrameAllocator> = SpinMutex::new("FRAMES", FrameAllocator::new());
+
+// CPU (+ 1), die 'FRAMES' gerade haelt, 0 = keine
+static OWNER: AtomicUsize = AtomicUsize::new(0);
+
+// Je CPU ein Frame fuer Seitenfehler waehrend 'FRAMES' gesperrt ist, 0 = leer
+static RESERVE: [AtomicU64; consts::MAX_CPUS] = [const { AtomicU64::new(0) }; consts::MAX_CPUS];
+
+struct FrameAllocator {
+    base: u64,         // physikalische Adresse von Frame 0 der Bitmap
+    bitmap: Vec<u64>,  // Bit gesetzt = Frame belegt (oder kein freier Speicher)
+    frames: usize,     // Anzahl Frames in der Bitmap
+    total: usize,      // Anzahl verwaltbarer Frames
+    free: usize,       // davon frei
+    next: usize,       // ab hier wird der naechste freie Frame gesucht
+}
+
+impl FrameAllocator {
+    const fn new() -> FrameAllocator {
+        FrameAllocator {
+            base: 0,
+            bitmap: Vec::new(),
+            frames: 0,
+            total: 0,
+            free: 0,
+            next: 0,
+        }
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS] & (1 << (frame % BITS)) != 0
+    }
+
+    fn set_used(&mut self, frame: usize, used: bool) {
+        if used {
+            self.bitmap[frame / BITS] |= 1 << (frame % BITS);
+        } else {
+            self.bitmap[frame / BITS] &= !(1 << (frame % BITS));
+        }
+    }
+
+    // Nummer des Frames in der Bitmap, Panic bei ungueltiger Adresse
+    fn frame_of(&self, addr: u64) -> usize {
+        if !addr.is_multiple_of(FRAME_SIZE) || addr < self.base {
+            panic!("frames: invalid frame address 0x{:x}", addr);
+        }
+        let frame = ((addr - self.base) / FRAME_SIZE) as usize;
+        if frame >= self.frames {
+            panic!("frames: invalid frame address 0x{:x}", addr);
+        }
+        frame
+    }
+
+    fn alloc(&mut self) -> Option<u64> {
+        if self.free == 0 {
+            return None;
+        }
+        // Ganze belegte Eintraege ueberspringen, ab 'next' und dann von vorne
+        let words = self.bitmap.len();
+        let first = self.next / BITS;
+        for i in 0..words {
+            let w = (first + i) % words;
+            if self.bitmap[w] != u64::MAX {
+                let frame = w * BITS + (!self.bitmap[w]).trailing_zeros() as usize;
+                self.set_used(frame, true);
+                self.free -= 1;
+                self.next = frame + 1;
+                return Some(self.base + frame as u64 * FRAME_SIZE);
+            }
+        }
+        None
+    }
+
+    fn alloc_contiguous(&mut self, n: usize) -> Option<u64> {
+        if n == 0 || n > self.free {
+            return None;
+        }
+        let mut start = 0;
+        let mut len = 0;
+        for frame in 0..self.frames {
+            if self.is_used(frame) {
+                len = 0;
+                start = frame + 1;
+                continue;
+            }
+            len += 1;
+            if len == n {
+                for f in start..start + n {
+                    self.set_used(f, true);
+                }
+                self.free -= n;
+                return Some(self.base + start as u64 * FRAME_SIZE);
+            }
+        }
+        None
+    }
+
+    fn free(&mut self, addr: u64, n: usize) {
+        let first = self.frame_of(addr);
+        for frame in first..first + n {
+            if frame >= self.frames || !self.is_used(frame) {
+                panic!("frames: freeing unused frame 0x{:x}", self.base + frame as u64 * FRAME_SIZE);
+            }
+            self.set_used(frame, false);
+        }
+        self.free += n;
+        if first < self.next {
+            self.next = first;
+        }
+    }
+}
+
+// 'f' mit gesperrtem 'FRAMES' ausfuehren und danach die Reserve dieser CPU auffuellen
+fn with_frames<R>(f: impl FnOnce(&mut FrameAllocator) -> R) -> R {
+    let was_enabled = cpu::disable_int_nested();
+    let me = smp::cpu_id() + 1;
+    let mut fa = FRAMES.lock();
+    OWNER.store(me, Ordering::SeqCst);
+    let result = f(&mut fa);
+    let reserve = &RESERVE[me - 1];
+    if reserve.load(Ordering::SeqCst) == 0 {
+        if let Some(frame) = fa.alloc() {
+            reserve.store(frame, Ordering::SeqCst);
+        }
+    }
+    drop(fa);
+    // Erst nach dem Freigeben, ein Seitenfehler beim Freigeben nimmt dann die Reserve.
+    // Eine andere CPU kann den Lock inzwischen haben, dann bleibt 'OWNER' stehen.
+    let _ = OWNER.compare_exchange(me, 0, Ordering::SeqCst, Ordering::SeqCst);
+    cpu::enable_int_nested(was_enabled);
+    result
+}
+
+/**
+ Description: Initialize the frame allocator with the free physical memory,
+              see `multiboot::get_free_memory`. Regions are shrunk to whole
+              frames. Must be called once after the heap allocator.
+*/
+pub fn init(free_regions: &[PhysRegion]) {
+    let first = |r: &PhysRegion| r.start.div_ceil(FRAME_SIZE); // aufrunden
+    let last = |r: &PhysRegion| (r.end + 1) / FRAME_SIZE;                   // exklusiv, abrunden
+
+    let usable: Vec<(u64, u64)> = free_regions
+        .iter()
+        .map(|r| (first(r), last(r)))
+        .filter(|(s, e)| s < e)
+        .collect();
+    if usable.is_empty() {
+        panic!("frames::init: no free memory");
+    }
+    let low = usable.iter().map(|(s, _)| *s).min().unwrap();
+    let high = usable.iter().map(|(_, e)| *e).max().unwrap();
+
+    let frames = (high - low) as usize;
+    let mut fa = FrameAllocator {
+        base: low * FRAME_SIZE,
+        bitmap: vec![u64::MAX; frames.div_ceil(BITS)],
+        frames,
+        total: 0,
+        free: 0,
+        next: 0,
+    };
+    for (s, e) in usable {
+        for frame in (s - low) as usize..(e - low) as usize {
+            fa.set_used(frame, false);
+        }
+        fa.total += (e - s) as usize;
+    }
+    fa.free = fa.total;
+
+    with_frames(|frames| *frames = fa);
+}
+
+/**
+ Description: Allocate one frame (not zeroed)
+
+ Return: \
+    physical address of the frame or `None` if memory is exhausted
+*/
+pub fn alloc_frame() -> Option<u64> {
+    with_frames(|frames| frames.alloc())
+}
+
+/**
+ Description: Free a frame allocated by `alloc_frame`
+*/
+pub fn free_frame(addr: u64) {
+    free_contiguous(addr, 1);
+}
+
+/**
+ Description: Allocate `n` physically contiguous frames (not zeroed)
+
+ Return: \
+    physical address of the first frame or `None` if there is no such range
+*/
+pub fn alloc_contiguous(n: usize) -> Option<u64> {
+    with_frames(|frames| frames.alloc_contiguous(n))
+}
+
+/**
+ Description: Free `n` frames starting at `addr`, allocated by `alloc_contiguous`
+*/
+pub fn free_contiguous(addr: u64, n: usize) {
+    with_frames(|frames| frames.free(addr, n));
+}
+
+/**
+ Description: Same as `alloc_frame`, but for the page fault handler. If the
+              fault occurred while this cpu holds the lock, the reserve frame
+              of the cpu is taken instead of waiting forever.
+*/
+pub fn alloc_frame_for_fault() -> Option<u64> {
+    let me = smp::cpu_id() + 1;
+    loop {
+        if OWNER.load(Ordering::SeqCst) == me {
+            let frame = RESERVE[me - 1].swap(0, Ordering::SeqCst);
+            return if frame == 0 { None } else { Some(frame) };
+        }
+        if let Some(mut frames) = FRAMES.try_lock() {
+            return frames.alloc();
+        }
+        cpu::pause();
+    }
+}
+
+/**
+ Description: Free a frame allocated by `alloc_frame_for_fault` in the same
+              page fault handler
+*/
+pub fn free_frame_for_fault(addr: u64) {
+    let me = smp::cpu_id() + 1;
+    loop {
+        if OWNER.load(Ordering::SeqCst) == me {
+            // Der Frame kam aus der nun leeren Reserve
+            RESERVE[me - 1].store(addr, Ordering::SeqCst);
+            return;
+        }
+        if let Some(mut frames) = FRAMES.try_lock() {
+            frames.free(addr, 1);
+            return;
+        }
+        cpu::pause();
+    }
+}
+
+/**
+ Description: Number of frames managed by the allocator
+*/
+pub fn total_frames() -> usize {
+    with_frames(|frames| frames.total)
+}
+
+/**
+ Description: Number of free frames
+*/
+pub fn free_frames() -> usize {
+    with_frames(|frames| frames.free)
+}
@@ -15,12 +15,23 @@
 use crate::devices::kprint;
 use crate::kernel::cpu;
 use crate::kernel::interrupts::isr;
+use crate::kernel::lockdep;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::thread;
 use alloc::{boxed::Box, vec::Vec};
+use core::ptr;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+pub const INT_VEC_PAGE_FAULT: usize = 14; // Fehlercode, wird in 'pagefault.rs' behandelt
 pub const INT_VEC_TIMER: usize = 32;
 pub const INT_VEC_KEYBOARD: usize = 33;
 pub const INT_VEC_SB16: usize = 37;
+pub const INT_VEC_SYSCALL: usize = 0x80; // Systemaufrufe, wird in 'syscall.rs' behandelt
+
+// Interrupt Descriptor Table in 'interrupts.asm', 16 Bytes je Eintrag
+extern "C" {
+    static mut _idt: [u8; MAX_VEC_NUM * 16];
+}
 
 /**
  Description:
@@ -37,7 +48,10 @@ pub extern "C" fn int_disp(vector: u32) {
     }
 
     // 'report' calls registered ISR
-    if report(vector as usize) == true {
+    lockdep::enter_interrupt();
+    let handled = report(vector as usize);
+    lockdep::leave_interrupt();
+    if handled == true {
         return;
     }
 
@@ -115,6 +129,40 @@ pub fn register(vector: usize, isr: Box<dyn isr::ISR>) -> bool {
     return false;
 }
 
+/**
+ Description:
+    Allow `int vector` in ring 3 by setting the DPL of the interrupt gate to 3.
+    Otherwise the instruction causes a general protection fault.
+
+ Parameters: \
+    `vector` vector number of interrupt
+*/
+pub fn allow_user(vector: usize) {
+    if vector < MAX_VEC_NUM {
+        unsafe {
+            // Byte 5: present, DPL = 3, 64 bit interrupt gate
+            (*ptr::addr_of_mut!(_idt))[vector * 16 + 5] = 0xee;
+        }
+    }
+}
+
+/**
+ Description:
+    Let the cpu switch to the stack in entry `ist` (1..7) of the interrupt
+    stack table for `vector`, see `smp::set_ist`. 0 keeps the current stack.
+
+ Parameters: \
+    `vector` vector number of interrupt
+*/
+pub fn set_ist(vector: usize, ist: usize) {
+    if vector < MAX_VEC_NUM && ist <= 7 {
+        unsafe {
+            // Byte 4: Bits 0..2 = IST
+            (*ptr::addr_of_mut!(_idt))[vector * 16 + 4] = ist as u8;
+        }
+    }
+}
+
 /**
 Description:
    Check if an ISR is registered for `vector`. If so, call it.
@@ -183,6 +231,7 @@ fn print_exception(vector: u32) {
 /**
 Description:
    Handling a general proection fault. Called from assembly 'interrupts.asm'
+   A fault in ring 3 ends the user thread, otherwise the cpu spins forever.
 
 Parameters: \
    `rip`         address of the instruction which caused the GPF \
@@ -202,5 +251,9 @@ pub extern "C" fn int_gpf(error_code: u64, cs: u16, rip: u64) {
         cs,
         rip
     );
+    // Ein User-Thread haelt keine Kernel-Locks und kann beendet werden
+    if cs & 3 == 3 {
+        Scheduler::exit_with_code(thread::EXIT_CODE_FAULT);
+    }
     loop {}
 }
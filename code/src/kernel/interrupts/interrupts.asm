@@ -10,9 +10,12 @@
 ;╚═════════════════════════════════════════════════════════════════════════╝
 [GLOBAL _init_interrupts]     ; export init function
 [GLOBAL _idt]                 ; export, needed in 'syscalls.asm'
+[GLOBAL _load_idt]            ; export, needed by the application processors in 'smp.rs'
 
 [EXTERN int_disp]             ; Funktion in Rust, welche Interrupts behandelt
 [EXTERN int_gpf]              ; Funktion in Rust, welche GPF behandelt
+[EXTERN int_pf]               ; Funktion in Rust, welche Seitenfehler behandelt
+[EXTERN int_syscall]          ; Funktion in Rust, welche Systemaufrufe behandelt
 
 [SECTION .text]
 [BITS 64]
@@ -45,12 +48,31 @@ _wrapper_%1:
    push   r14
    push   r15
 
-   ; do we have a general protection fault?
+   ; do we have a general protection fault? Auch hier liegt ein Fehlercode (15 Register = 120 Bytes)
 			%if %1 == 13 
-	     mov    rdi, [rsp+112] ; error code
-	     mov    rdx, [rsp+120] ; rip
-	     mov    rsi, [rsp+128] ; cs
+	     mov    rdi, [rsp+120] ; error code
+	     mov    rdx, [rsp+128] ; rip
+	     mov    rsi, [rsp+136] ; cs
+	     sub    rsp, 8         ; rsp wegen des Fehlercodes auf 16 Bytes ausrichten
 	    call    int_gpf
+	     add    rsp, 8
+   ; page fault? Die CPU hat einen Fehlercode abgelegt (15 Register = 120 Bytes)
+   %elif %1 == 14
+	     mov    rdi, [rsp+120] ; error code
+	     mov    rsi, [rsp+128] ; rip
+	     mov    rdx, [rsp+136] ; cs
+	     lea    rcx, [rsp+128] ; Rahmen fuer 'iretq', wird bei Fehlern im Ring 3 geaendert
+	     sub    rsp, 8         ; rsp wegen des Fehlercodes auf 16 Bytes ausrichten
+	     call   int_pf
+	     add    rsp, 8
+   ; Systemaufruf aus dem Ring 3: Nummer in rax, Parameter in rdi, rsi, rdx
+   %elif %1 == 0x80
+	     mov    rcx, rdx
+	     mov    rdx, rsi
+	     mov    rsi, rdi
+	     mov    rdi, rax
+	     call   int_syscall
+	     mov    [rsp+112], rax ; Rueckgabewert statt des gesicherten rax
    %else
    	  ; pass the vector as parameter 
 	     xor rax, rax
@@ -76,6 +98,11 @@ _wrapper_%1:
    pop    rbx
 	  pop    rax
 
+   ; Fehlercode des Seitenfehlers entfernen
+   %if %1 == 14
+	     add    rsp, 8
+   %endif
+
 	  ; done!
   	iretq
 %endmacro
@@ -113,6 +140,13 @@ _loop:
 	  lidt   [_idt_descr]
 	  ret
 
+;
+; Load the IDT (already set up by '_setup_idt') on an application processor
+;
+_load_idt:
+	  lidt   [_idt_descr]
+	  ret
+
 ;
 ; Reprogramming the Programmable Interrupt Controllers (PICs) 
 ; so that all 15 hardware interrupts lie sequentially in the IDT
@@ -0,0 +1,487 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: lockdep                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Lock-order validator, enabled with 'consts::LOCKDEP'. Each lock ║
+   ║         belongs to a lock class, either its own (global locks) or the   ║
+   ║         class of the code location which created the lock, so that      ║
+   ║         e.g. all mutexes of channels share one class. When a lock is    ║
+   ║         acquired while others are held, the order "held -> new" is      ║
+   ║         recorded. If the reverse order was recorded before (also        ║
+   ║         indirectly over other classes), a deadlock is possible and      ║
+   ║         reported on the serial port.                                    ║
+   ║         Blocking calls in interrupt context are reported, too.          ║
+   ║                                                                         ║
+   ║         Tracked are 'SpinMutex', 'Spinlock' (thus all wait queues),     ║
+   ║         'Mutex' and 'RwLock' (readers and writers share a class). Not   ║
+   ║         tracked are semaphores, condition variables and                 ║
+   ║         'JoinHandle::join', as they have no owner which could release   ║
+   ║         them; of these only 'Semaphore::p' is checked for interrupt     ║
+   ║         context.                                                        ║
+   ║                                                                         ║
+   ║         The held locks are recorded per cpu and saved in the thread on  ║
+   ║         each thread switch (see 'Thread::switch'). The tables have a    ║
+   ║         fixed size, as the allocator lock itself is tracked.            ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::fmt::Write;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::consts;
+use crate::devices::serial;
+use crate::kernel::cpu;
+use crate::kernel::smp;
+
+// Maximale Anzahl Lock-Klassen (Bits in 'DEPS')
+const MAX_CLASSES: usize = 64;
+// Maximale Anzahl gleichzeitig gehaltener Locks eines Threads
+const MAX_HELD: usize = 16;
+
+// Werte von 'LockClass::id'
+const UNREGISTERED: usize = 0;
+const UNTRACKED: usize = usize::MAX; // Tabelle war voll
+
+/**
+ Description: Lock class, usually one per lock or one per code location
+              creating locks
+*/
+pub struct LockClass {
+    name: &'static str,
+    site: Option<&'static Location<'static>>, // Erzeugungsort, gleiche Orte teilen sich eine Klasse
+    id: AtomicUsize, // Index in 'CLASSES' + 1, UNREGISTERED oder UNTRACKED
+}
+
+impl LockClass {
+    /**
+     Description: Own class of a single lock, e.g. of a global `SpinMutex`
+    */
+    pub const fn new(name: &'static str) -> LockClass {
+        LockClass {
+            name,
+            site: None,
+            id: AtomicUsize::new(UNREGISTERED),
+        }
+    }
+
+    /**
+     Description: Class shared by all locks named `name` created at `site`,
+                  e.g. `Location::caller()` in a `#[track_caller]` constructor.
+                  Needs only one slot in the fixed table, even if locks are
+                  created again and again.
+    */
+    pub const fn at(name: &'static str, site: &'static Location<'static>) -> LockClass {
+        LockClass {
+            name,
+            site: Some(site),
+            id: AtomicUsize::new(UNREGISTERED),
+        }
+    }
+
+    // Index der Klasse, wird beim ersten Aufruf vergeben
+    fn index(&self) -> Option<usize> {
+        match self.id.load(Ordering::SeqCst) {
+            UNTRACKED => None,
+            UNREGISTERED => self.register(),
+            id => Some(id - 1),
+        }
+    }
+
+    fn register(&self) -> Option<usize> {
+        // Klasse des Erzeugungsortes schon registriert? (Bei gleichzeitigem
+        // Registrieren auf zwei CPUs kann sie zwei Eintraege bekommen.)
+        if self.site.is_some() {
+            let count = NUM_CLASSES.load(Ordering::SeqCst).min(MAX_CLASSES);
+            if let Some(idx) = (0..count).find(|i| CLASSES[*i].is(self)) {
+                self.id.store(idx + 1, Ordering::SeqCst);
+                return Some(idx);
+            }
+        }
+
+        let idx = NUM_CLASSES.fetch_add(1, Ordering::SeqCst);
+        if idx >= MAX_CLASSES {
+            self.id.store(UNTRACKED, Ordering::SeqCst);
+            if !TABLE_FULL.swap(true, Ordering::SeqCst) {
+                report(format_args!("lockdep: too many lock classes, {} is not tracked\n", self.name));
+            }
+            return None;
+        }
+        CLASSES[idx].set(self);
+        // Falls eine andere CPU die Klasse gleichzeitig registriert hat, bleibt 'idx' ungenutzt
+        match self.id.compare_exchange(UNREGISTERED, idx + 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => Some(idx),
+            Err(id) if id == UNTRACKED => None,
+            Err(id) => Some(id - 1),
+        }
+    }
+}
+
+// Name und Adresse (bzw. Erzeugungsort) einer registrierten Klasse fuer die Meldungen
+struct ClassInfo {
+    name_ptr: AtomicUsize,
+    name_len: AtomicUsize,
+    addr: AtomicUsize,
+    site: AtomicUsize, // &'static Location oder 0
+}
+
+impl ClassInfo {
+    const fn new() -> ClassInfo {
+        ClassInfo {
+            name_ptr: AtomicUsize::new(0),
+            name_len: AtomicUsize::new(0),
+            addr: AtomicUsize::new(0),
+            site: AtomicUsize::new(0),
+        }
+    }
+
+    fn set(&self, class: &LockClass) {
+        self.name_len.store(class.name.len(), Ordering::SeqCst);
+        self.addr.store(class as *const LockClass as usize, Ordering::SeqCst);
+        let site = class.site.map_or(0, |s| s as *const Location as usize);
+        self.site.store(site, Ordering::SeqCst);
+        // zuletzt, 'is' und 'name' pruefen 'name_ptr'
+        self.name_ptr.store(class.name.as_ptr() as usize, Ordering::SeqCst);
+    }
+
+    fn site(&self) -> Option<&'static Location<'static>> {
+        let site = self.site.load(Ordering::SeqCst) as *const Location<'static>;
+        unsafe { site.as_ref() }
+    }
+
+    // Gehoert 'class' zu dieser Klasse (gleicher Name und Erzeugungsort)?
+    fn is(&self, class: &LockClass) -> bool {
+        if self.name_ptr.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+        match (self.site(), class.site) {
+            (Some(a), Some(b)) => {
+                self.name() == class.name && a.file() == b.file() && a.line() == b.line() && a.column() == b.column()
+            }
+            _ => false,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        let ptr = self.name_ptr.load(Ordering::SeqCst) as *const u8;
+        if ptr.is_null() {
+            return "?";
+        }
+        let len = self.name_len.load(Ordering::SeqCst);
+        unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) }
+    }
+}
+
+impl fmt::Display for ClassInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.site() {
+            Some(site) => write!(f, "{} ({}:{})", self.name(), site.file(), site.line()),
+            None => write!(f, "{}@{:x}", self.name(), self.addr.load(Ordering::SeqCst)),
+        }
+    }
+}
+
+static CLASSES: [ClassInfo; MAX_CLASSES] = [const { ClassInfo::new() }; MAX_CLASSES];
+static NUM_CLASSES: AtomicUsize = AtomicUsize::new(0);
+static TABLE_FULL: AtomicBool = AtomicBool::new(false);
+
+// Bit j in DEPS[i]: Klasse j wurde geholt, waehrend Klasse i gehalten wurde
+static DEPS: [AtomicU64; MAX_CLASSES] = [const { AtomicU64::new(0) }; MAX_CLASSES];
+// Bit j in REPORTED[i]: Umkehrung der Reihenfolge i -> j wurde schon gemeldet
+static REPORTED: [AtomicU64; MAX_CLASSES] = [const { AtomicU64::new(0) }; MAX_CLASSES];
+
+/**
+ Description: Locks held by a thread (or a cpu before threads run) and the
+              nesting depth of interrupt handlers. Stored in each thread.
+*/
+#[derive(Clone, Copy)]
+pub struct HeldLocks {
+    classes: [u8; MAX_HELD],
+    depth: usize,
+    irq_depth: usize, // > 0 innerhalb von 'int_disp'
+    overflow: bool,   // mehr als MAX_HELD Locks, weitere werden ignoriert
+}
+
+impl HeldLocks {
+    pub const fn new() -> HeldLocks {
+        HeldLocks {
+            classes: [0; MAX_HELD],
+            depth: 0,
+            irq_depth: 0,
+            overflow: false,
+        }
+    }
+
+    fn held(&self) -> &[u8] {
+        &self.classes[..self.depth]
+    }
+
+    fn push(&mut self, idx: usize) {
+        if self.depth == MAX_HELD {
+            if !self.overflow {
+                self.overflow = true;
+                report(format_args!("lockdep: more than {} locks held, not tracked\n", MAX_HELD));
+            }
+            return;
+        }
+        self.classes[self.depth] = idx as u8;
+        self.depth += 1;
+    }
+
+    // Locks werden nicht unbedingt in umgekehrter Reihenfolge freigegeben
+    fn remove(&mut self, idx: usize) {
+        if let Some(pos) = self.held().iter().rposition(|c| *c as usize == idx) {
+            self.classes.copy_within(pos + 1..self.depth, pos);
+            self.depth -= 1;
+        }
+    }
+}
+
+impl fmt::Display for HeldLocks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, c) in self.held().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", CLASSES[*c as usize])?;
+        }
+        write!(f, "]")
+    }
+}
+
+// Gehaltene Locks des laufenden Threads je CPU, nur mit gesperrten Interrupts benutzt
+struct CpuHeld(UnsafeCell<HeldLocks>);
+
+unsafe impl Sync for CpuHeld {}
+
+static CPU_HELD: [CpuHeld; consts::MAX_CPUS] = [const { CpuHeld(UnsafeCell::new(HeldLocks::new())) }; consts::MAX_CPUS];
+
+fn with_held<R, F: FnOnce(&mut HeldLocks) -> R>(f: F) -> R {
+    let was_enabled = cpu::disable_int_nested();
+    let held = unsafe { &mut *CPU_HELD[smp::cpu_id()].0.get() };
+    let res = f(held);
+    cpu::enable_int_nested(was_enabled);
+    res
+}
+
+// Meldungen direkt auf COM1 ausgeben, 'kprint' nutzt selbst ein Lock
+fn report(args: fmt::Arguments) {
+    unsafe {
+        let _ = (*core::ptr::addr_of_mut!(serial::COM1)).write_fmt(args);
+    }
+}
+
+// Ist 'to' von 'from' aus ueber aufgezeichnete Reihenfolgen erreichbar?
+fn reaches(from: usize, to: usize) -> bool {
+    let mut reach = DEPS[from].load(Ordering::SeqCst);
+    let mut done: u64 = 0;
+    loop {
+        let todo = reach & !done;
+        if todo == 0 {
+            break;
+        }
+        let i = todo.trailing_zeros() as usize;
+        done |= 1 << i;
+        reach |= DEPS[i].load(Ordering::SeqCst);
+    }
+    reach & (1 << to) != 0
+}
+
+// Reihenfolge 'held' -> 'new' aufzeichnen und gegen die bisherigen pruefen
+fn add_dependency(held: usize, new: usize, all_held: &HeldLocks) {
+    if DEPS[held].load(Ordering::SeqCst) & (1 << new) != 0 {
+        return;
+    }
+    if reaches(new, held) && REPORTED[held].fetch_or(1 << new, Ordering::SeqCst) & (1 << new) == 0 {
+        report(format_args!(
+            "lockdep: lock order inversion on cpu {}: acquiring {} while holding {},\n\
+             \x20        but {} was acquired before while holding {}\n\
+             \x20        held locks: {}\n",
+            smp::cpu_id(),
+            CLASSES[new],
+            CLASSES[held],
+            CLASSES[held],
+            CLASSES[new],
+            all_held
+        ));
+    }
+    DEPS[held].fetch_or(1 << new, Ordering::SeqCst);
+}
+
+/**
+ Description: Called before a lock of `class` is acquired (and may be waited
+              for). Records and checks the order against the held locks.
+*/
+pub fn acquire(class: &LockClass) {
+    if !consts::LOCKDEP {
+        return;
+    }
+    let idx = match class.index() {
+        Some(idx) => idx,
+        None => return,
+    };
+    with_held(|held| {
+        for h in held.held().iter().map(|c| *c as usize) {
+            if h == idx {
+                report(format_args!(
+                    "lockdep: recursive locking of {} on cpu {}, held locks: {}\n",
+                    CLASSES[idx],
+                    smp::cpu_id(),
+                    held
+                ));
+            } else {
+                add_dependency(h, idx, held);
+            }
+        }
+        held.push(idx);
+    });
+}
+
+/**
+ Description: Called after a lock of `class` has been acquired by a 'try_lock'.
+              A 'try_lock' cannot deadlock, so no order is recorded.
+*/
+pub fn acquired_try(class: &LockClass) {
+    if !consts::LOCKDEP {
+        return;
+    }
+    if let Some(idx) = class.index() {
+        with_held(|held| held.push(idx));
+    }
+}
+
+/**
+ Description: Called when a lock of `class` is released
+*/
+pub fn release(class: &LockClass) {
+    if !consts::LOCKDEP {
+        return;
+    }
+    if let Some(idx) = class.index() {
+        with_held(|held| held.remove(idx));
+    }
+}
+
+/**
+ Description: Called by functions which may block the calling thread.
+              Reports a call in interrupt context.
+
+ Parameters: \
+    `what` name of the blocking function
+*/
+pub fn might_block(what: &str) {
+    if !consts::LOCKDEP {
+        return;
+    }
+    with_held(|held| {
+        if held.irq_depth > 0 {
+            report(format_args!(
+                "lockdep: blocking {} called in interrupt context on cpu {}, held locks: {}\n",
+                what,
+                smp::cpu_id(),
+                held
+            ));
+        }
+    });
+}
+
+/**
+ Description: Called by `int_disp` before and after the ISR
+*/
+pub fn enter_interrupt() {
+    if consts::LOCKDEP {
+        with_held(|held| held.irq_depth += 1);
+    }
+}
+
+pub fn leave_interrupt() {
+    if consts::LOCKDEP {
+        with_held(|held| held.irq_depth -= 1);
+    }
+}
+
+/**
+ Description: Save the held locks of the running thread in `now` and
+              continue with those of `then`. Called by `Thread::switch`.
+*/
+pub fn switch_thread(now: &mut HeldLocks, then: &HeldLocks) {
+    if consts::LOCKDEP {
+        with_held(|held| {
+            *now = *held;
+            *held = *then;
+        });
+    }
+}
+
+/**
+ Description: `spin::Mutex` checked by lockdep, used for the global kernel
+              locks like `SCHEDULER` or `kprint::WRITER`.
+*/
+pub struct SpinMutex<T> {
+    inner: spin::Mutex<T>,
+    class: LockClass,
+}
+
+impl<T> SpinMutex<T> {
+    pub const fn new(name: &'static str, data: T) -> SpinMutex<T> {
+        SpinMutex {
+            inner: spin::Mutex::new(data),
+            class: LockClass::new(name),
+        }
+    }
+
+    pub fn lock(&self) -> SpinMutexGuard<'_, T> {
+        acquire(&self.class);
+        SpinMutexGuard {
+            inner: self.inner.lock(),
+            class: &self.class,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<SpinMutexGuard<'_, T>> {
+        let inner = self.inner.try_lock()?;
+        acquired_try(&self.class);
+        Some(SpinMutexGuard {
+            inner,
+            class: &self.class,
+        })
+    }
+
+    /**
+     Description: Free the lock without a guard, only for printing a panic
+    */
+    pub unsafe fn force_unlock(&self) {
+        release(&self.class);
+        self.inner.force_unlock();
+    }
+}
+
+/**
+Description: Guard of a `SpinMutex`, releases the lock when dropped
+*/
+pub struct SpinMutexGuard<'a, T> {
+    inner: spin::MutexGuard<'a, T>,
+    class: &'a LockClass,
+}
+
+impl<'a, T> Deref for SpinMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, T> DerefMut for SpinMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<'a, T> Drop for SpinMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        release(self.class);
+    }
+}
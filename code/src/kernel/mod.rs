@@ -4,4 +4,13 @@ pub mod allocator;
 pub mod interrupts;
 pub mod corouts;
 pub mod stack;
-pub mod threads;
\ No newline at end of file
+pub mod threads;
+pub mod acpi;
+pub mod smp;
+pub mod lockdep;
+pub mod executor;
+pub mod frames;
+pub mod paging;
+pub mod address_space;
+pub mod pagefault;
+pub mod syscall;
\ No newline at end of file
@@ -0,0 +1,189 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pagefault                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Page fault handler (#PF). The faulting address is read from     ║
+   ║         CR2, the error code is decoded into 'PageFaultError'. Faults on ║
+   ║         not yet backed pages of a thread stack are resolved (see        ║
+   ║         'stack'), all others are reported with the faulting thread.     ║
+   ║         A fault in ring 3 ends the thread, a fault in the kernel halts  ║
+   ║         the cpu.                                                        ║
+   ║                                                                         ║
+   ║         The handler runs on its own stack per cpu (interrupt stack      ║
+   ║         table), as a fault on the kernel stack would otherwise fault    ║
+   ║         again while the cpu saves the interrupt frame. A faulting user  ║
+   ║         thread is not ended on this stack, a fault on the same cpu      ║
+   ║         could overwrite it before the thread switch. Instead the        ║
+   ║         handler returns with 'iretq' to 'exit_faulted' in ring 0 on the ║
+   ║         (unused) kernel stack of the thread.                            ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::fmt;
+use core::ptr;
+
+use crate::consts;
+use crate::devices::kprint;
+use crate::kernel::cpu;
+use crate::kernel::interrupts::intdispatcher::{self, INT_VEC_PAGE_FAULT};
+use crate::kernel::smp;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::stack::{self, Fault};
+use crate::kernel::threads::thread::{self, Thread};
+
+// Eintrag in der Interrupt Stack Table fuer Seitenfehler
+const IST_PAGE_FAULT: usize = 1;
+const FAULT_STACK_SIZE: usize = 0x4000;
+
+#[repr(C, align(16))]
+struct FaultStack([u8; FAULT_STACK_SIZE]);
+
+const NO_STACK: FaultStack = FaultStack([0; FAULT_STACK_SIZE]);
+static mut FAULT_STACKS: [FaultStack; consts::MAX_CPUS] = [NO_STACK; consts::MAX_CPUS];
+
+// RFLAGS fuer 'exit_faulted': nur das reservierte Bit 1, Interrupts gesperrt
+const RFLAGS_RESERVED: u64 = 1 << 1;
+
+// Von der CPU gesicherter Rahmen fuer 'iretq'
+#[repr(C)]
+pub struct InterruptFrame {
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+/**
+ Description: Decoded error code of a page fault
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct PageFaultError {
+    pub present: bool, // Seite vorhanden, also Verletzung der Zugriffsrechte
+    pub write: bool,   // Schreibzugriff
+    pub user: bool,    // im Ring 3
+    pub fetch: bool,   // beim Holen eines Befehls
+}
+
+impl PageFaultError {
+    pub fn decode(error_code: u64) -> PageFaultError {
+        PageFaultError {
+            present: error_code & (1 << 0) != 0,
+            write: error_code & (1 << 1) != 0,
+            user: error_code & (1 << 2) != 0,
+            fetch: error_code & (1 << 4) != 0,
+        }
+    }
+}
+
+impl fmt::Display for PageFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = if self.fetch {
+            "instruction fetch"
+        } else if self.write {
+            "write"
+        } else {
+            "read"
+        };
+        write!(
+            f,
+            "{} ({}, {} mode)",
+            if self.present { "protection violation" } else { "page not present" },
+            access,
+            if self.user { "user" } else { "kernel" }
+        )
+    }
+}
+
+/**
+ Description: Let cpu `cpu` handle page faults on its own stack. Called by
+              `startup` and by each application processor.
+*/
+pub fn init(cpu: usize) {
+    let stack = unsafe { ptr::addr_of!(FAULT_STACKS[cpu]) } as u64;
+    smp::set_ist(IST_PAGE_FAULT, stack + FAULT_STACK_SIZE as u64);
+    intdispatcher::set_ist(INT_VEC_PAGE_FAULT, IST_PAGE_FAULT);
+}
+
+/**
+ Description: Page fault handler, called from `interrupts.asm` with
+              interrupts disabled. Returns to the faulting instruction only
+              if the fault was resolved. A fault in ring 3 ends the thread
+              (see `exit_after_iret`), otherwise the cpu is halted.
+
+ Parameters: \
+    `error_code` see `PageFaultError` \
+    `rip`        address of the instruction which caused the fault \
+    `cs`         active `cs` when the fault occurred \
+    `frame`      frame for `iretq` on the stack of the handler
+*/
+#[no_mangle]
+pub extern "C" fn int_pf(error_code: u64, rip: u64, cs: u64, frame: *mut InterruptFrame) {
+    let addr = cpu::read_cr2();
+    let error = PageFaultError::decode(error_code);
+
+    let fault = if error.present { Fault::Unknown } else { stack::handle_fault(addr, error.user) };
+    if matches!(fault, Fault::Resolved) {
+        return;
+    }
+
+    // force unlock, we do not return to the faulting code
+    unsafe {
+        kprint::WRITER.force_unlock();
+    }
+    match fault {
+        Fault::Overflow { tid, user } => report_overflow(addr, tid, user, rip),
+        _ => report(addr, error, rip, cs),
+    }
+
+    // Ein User-Thread haelt keine Kernel-Locks und wird beendet
+    if error.user {
+        kprintln!(" - thread killed.");
+        exit_after_iret(frame);
+    } else {
+        kprintln!(" - processor halted.");
+        cpu::halt();
+    }
+}
+
+// Zugriff auf den Guard-Bereich eines Stacks melden
+fn report_overflow(addr: u64, tid: usize, user: bool, rip: u64) {
+    kprintln!(
+        "stack overflow in tid {} ({} stack) at 0x{:x}, rip = 0x{:x}",
+        tid,
+        if user { "user" } else { "kernel" },
+        addr,
+        rip
+    );
+}
+
+// Nicht behebbaren Seitenfehler mit dem betroffenen Thread melden
+fn report(addr: u64, error: PageFaultError, rip: u64, cs: u64) {
+    kprintln!(
+        "page fault at 0x{:x}: {}, cs:rip = 0x{:x}:0x{:x}",
+        addr,
+        error,
+        cs,
+        rip
+    );
+    let thread = Thread::current();
+    if !thread.is_null() {
+        kprintln!("   in thread {}", unsafe { &*thread });
+    }
+}
+
+// 'frame' so aendern, dass 'iretq' im Ring 0 in 'exit_faulted' landet, auf dem
+// Kernel-Stack des Threads ('rsp0' im TSS). Dieser ist leer, da der Thread im
+// Ring 3 lief. 'rsp' wie nach einem 'call' ausrichten.
+fn exit_after_iret(frame: *mut InterruptFrame) {
+    unsafe {
+        (*frame).rip = exit_faulted as *const () as u64;
+        (*frame).cs = smp::KERNEL_CODE_SELECTOR;
+        (*frame).rflags = RFLAGS_RESERVED;
+        (*frame).rsp = (smp::rsp0() & !0xf) - 8;
+        (*frame).ss = smp::KERNEL_DATA_SELECTOR;
+    }
+}
+
+// Laeuft nach einem Seitenfehler im Ring 3 auf dem Kernel-Stack des Threads
+extern "C" fn exit_faulted() -> ! {
+    Scheduler::exit_with_code(thread::EXIT_CODE_FAULT);
+}
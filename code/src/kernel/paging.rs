@@ -0,0 +1,353 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: paging                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Editing the 4-level page tables (PML4, PDPT, PD, PT). The boot  ║
+   ║         code ('boot.asm') identity maps the physical memory with 2 MB   ║
+   ║         pages. Mapping or unmapping a 4 KB page inside such a large     ║
+   ║         page splits it into a page table first. Page tables are         ║
+   ║         allocated with 'frames' and accessed through the identity       ║
+   ║         mapping.                                                        ║
+   ║                                                                         ║
+   ║         The functions without '_in' work on the active address space    ║
+   ║         (CR3). TLB entries are only invalidated on the calling cpu.     ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::arch::x86_64::__cpuid;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::kernel::cpu;
+use crate::kernel::frames;
+use crate::kernel::lockdep::SpinMutex;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+// Bits eines Eintrags
+pub const PAGE_PRESENT: u64 = 1 << 0;
+pub const PAGE_WRITABLE: u64 = 1 << 1;
+pub const PAGE_USER: u64 = 1 << 2;
+pub const PAGE_WRITE_THROUGH: u64 = 1 << 3;
+pub const PAGE_NO_CACHE: u64 = 1 << 4;
+pub const PAGE_ACCESSED: u64 = 1 << 5;
+pub const PAGE_DIRTY: u64 = 1 << 6;
+pub const PAGE_HUGE: u64 = 1 << 7; // 2 MB (PD) bzw. 1 GB (PDPT)
+pub const PAGE_GLOBAL: u64 = 1 << 8;
+pub const PAGE_NO_EXECUTE: u64 = 1 << 63;
+
+// Physikalische Adresse in einem Eintrag
+pub const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+// EFER-Register und NXE-Bit (No-Execute Enable)
+const MSR_EFER: u32 = 0xC000_0080;
+const EFER_NXE: u64 = 1 << 11;
+
+// Wird von 'init' gesetzt, falls die CPU das NX-Bit kennt
+static NX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Serialisiert Aenderungen an den Seitentabellen
+static PAGING: SpinMutex<()> = SpinMutex::new("PAGING", ());
+
+extern "C" {
+    static _pml4: u8; // in 'boot.asm'
+}
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [u64; 512],
+}
+
+// Seitentabelle an der physikalischen Adresse 'addr' (identisch abgebildet).
+// 'addr' muss eine Seitentabelle sein, Aenderungen nur mit gesperrtem 'PAGING'
+// oder an Tabellen, die nur dem Aufrufer gehoeren.
+pub(crate) unsafe fn table(addr: u64) -> &'static mut PageTable {
+    &mut *(addr as *mut PageTable)
+}
+
+// Index in der Tabelle der Stufe 'level' (4 = PML4, 1 = PT)
+pub fn index(virt: u64, level: usize) -> usize {
+    ((virt >> (12 + 9 * (level - 1))) & 0x1ff) as usize
+}
+
+// Groesse des Bereichs, den ein Eintrag der Stufe 'level' abbildet
+pub fn page_size(level: usize) -> u64 {
+    PAGE_SIZE << (9 * (level - 1))
+}
+
+// Neue, leere Seitentabelle anlegen
+pub(crate) fn new_table() -> Option<u64> {
+    let frame = frames::alloc_frame()?;
+    unsafe { ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE as usize) };
+    Some(frame)
+}
+
+// Grosse Seite in 'entry' (Stufe 'level') in eine Tabelle mit 512 kleineren Seiten aufteilen.
+// 'entry' muss ein Eintrag mit 'PAGE_HUGE' sein, siehe 'table'.
+pub(crate) unsafe fn split(entry: &mut u64, level: usize) -> bool {
+    let new = match new_table() {
+        Some(t) => t,
+        None => return false,
+    };
+    let child_size = page_size(level - 1);
+    let base = *entry & ADDR_MASK & !(page_size(level) - 1);
+    let mut flags = *entry & !ADDR_MASK;
+    if level - 1 == 1 {
+        flags &= !PAGE_HUGE; // in einer PT gibt es keine grossen Seiten
+    }
+    let t = table(new);
+    for (i, e) in t.entries.iter_mut().enumerate() {
+        *e = (base + i as u64 * child_size) | flags;
+    }
+    *entry = new | (*entry & (PAGE_PRESENT | PAGE_WRITABLE | PAGE_USER));
+    true
+}
+
+// Kopie der Tabelle 'addr' ohne die Bits 'clear' in allen Eintraegen anlegen, siehe 'table'
+pub(crate) unsafe fn copy_table(addr: u64, clear: u64) -> Option<u64> {
+    let new = new_table()?;
+    let was_enabled = cpu::disable_int_nested();
+    let guard = PAGING.lock();
+    for (dst, src) in table(new).entries.iter_mut().zip(table(addr).entries.iter()) {
+        *dst = *src & !clear;
+    }
+    drop(guard);
+    cpu::enable_int_nested(was_enabled);
+    Some(new)
+}
+
+// Tabelle der naechsten Stufe holen, fehlende Tabellen anlegen, grosse Seiten aufteilen
+fn next_table(entry: &mut u64, level: usize, flags: u64) -> Option<u64> {
+    if *entry & PAGE_PRESENT == 0 {
+        *entry = new_table()? | PAGE_PRESENT | PAGE_WRITABLE;
+    } else if *entry & PAGE_HUGE != 0 && !unsafe { split(entry, level) } {
+        return None;
+    }
+    // Die Rechte aller Stufen werden verknuepft, daher hier nur erweitern
+    if flags & PAGE_USER != 0 {
+        *entry |= PAGE_USER;
+    }
+    Some(*entry & ADDR_MASK)
+}
+
+// Tabellen bis zur PT von 'virt' holen bzw. anlegen, 'PAGING' muss gesperrt sein
+fn walk_create(pml4: u64, virt: u64, flags: u64) -> Option<u64> {
+    let mut t = pml4;
+    for level in (2..=4).rev() {
+        t = next_table(unsafe { &mut table(t).entries[index(virt, level)] }, level, flags)?;
+    }
+    Some(t)
+}
+
+/**
+ Description: Enable the NX bit on the calling cpu, if supported.
+              Called by `startup` and by each application processor.
+*/
+pub fn init() {
+    let nx = __cpuid(0x8000_0001).edx & (1 << 20) != 0;
+    if nx {
+        cpu::wrmsr(MSR_EFER, cpu::rdmsr(MSR_EFER) | EFER_NXE);
+    }
+    NX_ENABLED.store(nx, Ordering::SeqCst);
+}
+
+/**
+ Description: Check if `PAGE_NO_EXECUTE` is supported, see `init`
+*/
+pub fn nx_enabled() -> bool {
+    NX_ENABLED.load(Ordering::SeqCst)
+}
+
+/**
+ Description: Physical address of the PML4 set up by `boot.asm`
+*/
+pub fn kernel_pml4() -> u64 {
+    ptr::addr_of!(_pml4) as u64
+}
+
+/**
+ Description: Physical address of the active PML4
+*/
+pub fn active_pml4() -> u64 {
+    cpu::read_cr3() & ADDR_MASK
+}
+
+/**
+ Description: Map the 4 KB page at `virt` to the frame `phys` in the address
+              space with the PML4 `pml4`. An existing mapping is replaced.
+
+ Parameters: \
+    `flags` combination of the `PAGE_*` bits, `PAGE_PRESENT` is added
+
+ Return: \
+    `false` if there was no frame for a page table
+*/
+pub fn map_in(pml4: u64, virt: u64, phys: u64, flags: u64) -> bool {
+    let mut flags = flags | PAGE_PRESENT;
+    if !nx_enabled() {
+        flags &= !PAGE_NO_EXECUTE;
+    }
+
+    let was_enabled = cpu::disable_int_nested();
+    let guard = PAGING.lock();
+    let pt = walk_create(pml4, virt, flags);
+    if let Some(t) = pt {
+        unsafe { table(t).entries[index(virt, 1)] = (phys & ADDR_MASK) | (flags & !PAGE_HUGE) };
+        cpu::invlpg(virt);
+    }
+    drop(guard);
+    cpu::enable_int_nested(was_enabled);
+    pt.is_some()
+}
+
+/**
+ Description: Create the page tables down to the PT for `virt` in the address
+              space with the PML4 `pml4`, without mapping the page itself.
+              Afterwards `entry_in` finds the entry of the page.
+
+ Parameters: \
+    `flags` only `PAGE_USER` is used, for the entries on the path
+
+ Return: \
+    `false` if there was no frame for a page table
+*/
+pub fn prepare_in(pml4: u64, virt: u64, flags: u64) -> bool {
+    let was_enabled = cpu::disable_int_nested();
+    let guard = PAGING.lock();
+    let ok = walk_create(pml4, virt, flags).is_some();
+    drop(guard);
+    cpu::enable_int_nested(was_enabled);
+    ok
+}
+
+/**
+ Description: Entry of the 4 KB page at `virt` in its PT, without a lock (e.g.
+              in the page fault handler). Page tables are only freed together
+              with their address space, so the entry stays valid.
+
+ Return: \
+    `None` if there is no PT for `virt` (see `prepare_in`) or `virt` is in a large page
+*/
+pub fn entry_in(pml4: u64, virt: u64) -> Option<&'static AtomicU64> {
+    let mut t = pml4;
+    for level in (2..=4).rev() {
+        let entry = unsafe { table(t).entries[index(virt, level)] };
+        if entry & PAGE_PRESENT == 0 || entry & PAGE_HUGE != 0 {
+            return None;
+        }
+        t = entry & ADDR_MASK;
+    }
+    unsafe {
+        let entry = &mut table(t).entries[index(virt, 1)] as *mut u64;
+        Some(AtomicU64::from_ptr(entry))
+    }
+}
+
+/**
+ Description: Remove the mapping of the 4 KB page at `virt` in the address
+              space with the PML4 `pml4`. The frame is not freed.
+
+ Return: \
+    the physical address of the frame, `None` if the page was not mapped or
+    lies in a large page which could not be split (no free frame)
+*/
+pub fn unmap_in(pml4: u64, virt: u64) -> Option<u64> {
+    let was_enabled = cpu::disable_int_nested();
+    let guard = PAGING.lock();
+    let mut t = pml4;
+    let mut phys = None;
+    for level in (1..=4).rev() {
+        let entry = unsafe { &mut table(t).entries[index(virt, level)] };
+        if *entry & PAGE_PRESENT == 0 {
+            break;
+        }
+        if level == 1 {
+            phys = Some(*entry & ADDR_MASK);
+            *entry = 0;
+            cpu::invlpg(virt);
+            break;
+        }
+        if *entry & PAGE_HUGE != 0 && !unsafe { split(entry, level) } {
+            break;
+        }
+        t = *entry & ADDR_MASK;
+    }
+    drop(guard);
+    cpu::enable_int_nested(was_enabled);
+    phys
+}
+
+/**
+ Description: Translate `virt` in the address space with the PML4 `pml4`
+
+ Return: \
+    the physical address, `None` if `virt` is not mapped
+*/
+pub fn translate_in(pml4: u64, virt: u64) -> Option<u64> {
+    let mut t = pml4;
+    for level in (1..=4).rev() {
+        let entry = unsafe { table(t).entries[index(virt, level)] };
+        if entry & PAGE_PRESENT == 0 {
+            return None;
+        }
+        if level == 1 || entry & PAGE_HUGE != 0 {
+            let size = page_size(level);
+            return Some((entry & ADDR_MASK & !(size - 1)) + (virt & (size - 1)));
+        }
+        t = entry & ADDR_MASK;
+    }
+    None
+}
+
+/**
+ Description: Check if `virt` is accessible in ring 3 in the address space
+              with the PML4 `pml4`, i.e. `PAGE_USER` is set on all levels.
+              Used to check pointers passed with system calls.
+*/
+pub fn is_user_in(pml4: u64, virt: u64) -> bool {
+    let mut t = pml4;
+    for level in (1..=4).rev() {
+        let entry = unsafe { table(t).entries[index(virt, level)] };
+        if entry & PAGE_PRESENT == 0 || entry & PAGE_USER == 0 {
+            return false;
+        }
+        if level == 1 || entry & PAGE_HUGE != 0 {
+            return true;
+        }
+        t = entry & ADDR_MASK;
+    }
+    false
+}
+
+/**
+ Description: Same as `map_in` for the active address space
+*/
+pub fn map(virt: u64, phys: u64, flags: u64) -> bool {
+    map_in(active_pml4(), virt, phys, flags)
+}
+
+/**
+ Description: Same as `unmap_in` for the active address space
+*/
+pub fn unmap(virt: u64) -> Option<u64> {
+    unmap_in(active_pml4(), virt)
+}
+
+/**
+ Description: Same as `translate_in` for the active address space
+*/
+pub fn translate(virt: u64) -> Option<u64> {
+    translate_in(active_pml4(), virt)
+}
+
+/**
+ Description: Invalidate the TLB entry of the page containing `virt`
+*/
+pub fn flush(virt: u64) {
+    cpu::invlpg(virt);
+}
+
+/**
+ Description: Invalidate all (non global) TLB entries of the calling cpu
+*/
+pub fn flush_all() {
+    cpu::write_cr3(cpu::read_cr3());
+}
@@ -0,0 +1,335 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: smp                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Symmetric multiprocessing. The processors are found in the ACPI ║
+   ║         MADT, the application processors (APs) are started one after   ║
+   ║         the other with INIT-SIPI-SIPI. Each AP gets its own GDT and TSS ║
+   ║         (the bootstrap processor uses those from 'boot.asm'), loads the ║
+   ║         shared IDT and waits until the scheduler is started.            ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use crate::consts;
+use crate::devices::lapic;
+use crate::devices::pit;
+use crate::kernel::acpi;
+use crate::kernel::cpu;
+use crate::kernel::pagefault;
+use crate::kernel::paging;
+use crate::kernel::stack;
+use crate::kernel::threads::scheduler;
+
+// Hierhin wird 'ap_boot.asm' kopiert, muss mit 'AP_BOOT_ADDR' dort uebereinstimmen
+const AP_BOOT_ADDR: usize = 0x8000;
+
+// Stack einer AP bis zum Start des ersten Threads
+const AP_STACK_SIZE: usize = 0x10000;
+
+// Selektoren, siehe GDT in 'boot.asm'
+pub const KERNEL_CODE_SELECTOR: u64 = 2 * 8;
+pub const KERNEL_DATA_SELECTOR: u64 = 3 * 8;
+const TSS_SELECTOR: u16 = 6 * 8;
+
+// In 'ap_boot.asm', 'boot.asm' und 'interrupts.asm'
+extern "C" {
+    static _ap_boot_start: u8;
+    static _ap_boot_end: u8;
+    static _ap_boot_cr3: u8;
+    static _ap_boot_stack: u8;
+    static _ap_boot_entry: u8;
+    static _ap_boot_cpu: u8;
+    static _gdt: u64;
+    fn _load_idt();
+}
+
+// Task State Segment ohne IO-Bitmap (104 Bytes)
+#[repr(C, packed)]
+struct Tss {
+    reserved0: u32,
+    rsp: [u64; 3], // rsp0 wird beim Threadwechsel gesetzt
+    reserved1: u64,
+    ist: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    iomap_base: u16,
+}
+
+// GDT und TSS einer AP
+#[repr(C, align(16))]
+struct PerCpu {
+    gdt: [u64; 8],
+    tss: Tss,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        PerCpu {
+            gdt: [0; 8],
+            tss: Tss {
+                reserved0: 0,
+                rsp: [0; 3],
+                reserved1: 0,
+                ist: [0; 7],
+                reserved2: 0,
+                reserved3: 0,
+                iomap_base: 104,
+            },
+        }
+    }
+}
+
+// Operand fuer 'lgdt'
+#[repr(C, packed)]
+struct GdtPointer {
+    limit: u16,
+    base: u64,
+}
+
+const PER_CPU_INIT: PerCpu = PerCpu::new();
+static mut PER_CPU: [PerCpu; consts::MAX_CPUS] = [PER_CPU_INIT; consts::MAX_CPUS];
+
+// CPU-Nummer je APIC-ID (8 Bit beim xAPIC), die CPU-Nummern werden in
+// Startreihenfolge vergeben
+static CPU_NUMBERS: [AtomicU8; 256] = [const { AtomicU8::new(0) }; 256];
+
+// Anzahl der laufenden CPUs
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+
+// Meldung der gerade startenden AP an 'start_ap'
+static AP_ONLINE: AtomicBool = AtomicBool::new(false);
+
+// Wird gesetzt, wenn die APs mit dem Scheduling beginnen duerfen
+static START_SCHEDULING: AtomicBool = AtomicBool::new(false);
+
+/**
+ Description: Number of the calling cpu (0 is the bootstrap processor)
+*/
+pub fn cpu_id() -> usize {
+    let count = CPU_COUNT.load(Ordering::SeqCst);
+    if count == 1 {
+        return 0;
+    }
+    CPU_NUMBERS[(lapic::id() & 0xff) as usize].load(Ordering::Relaxed) as usize
+}
+
+/**
+ Description: Number of running cpus
+*/
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::SeqCst)
+}
+
+/**
+ Description: Bit mask of the running cpus (bit n = cpu n), e.g. to check
+              affinity masks
+*/
+pub fn cpu_mask() -> u64 {
+    (1u64 << cpu_count()) - 1
+}
+
+/**
+ Description: Find all processors and start the application processors.
+              Called once by the bootstrap processor before the scheduler
+              is started. Without MADT only the bootstrap processor runs.
+*/
+pub fn init() {
+    let madt = match acpi::read_madt() {
+        Some(m) => m,
+        None => {
+            kprintln!("smp: no MADT found, running on one cpu");
+            return;
+        }
+    };
+    lapic::init_bsp(madt.lapic_address);
+    let bsp = lapic::id();
+    CPU_NUMBERS[(bsp & 0xff) as usize].store(0, Ordering::SeqCst);
+
+    // Zum Warten wird die Systemzeit benoetigt
+    let was_enabled = cpu::is_int_enabled();
+    cpu::enable_int();
+    for apic_id in madt.apic_ids.iter().map(|id| *id as u32) {
+        if apic_id == bsp {
+            continue;
+        }
+        if cpu_count() == consts::MAX_CPUS {
+            kprintln!("smp: more than {} cpus, ignoring the rest", consts::MAX_CPUS);
+            break;
+        }
+        start_ap(apic_id);
+    }
+    if was_enabled == false {
+        cpu::disable_int();
+    }
+    kprintln!("smp: {} cpu(s) online", cpu_count());
+}
+
+/**
+ Description: Let the application processors start scheduling. Called by
+              the bootstrap processor when all idle threads exist.
+*/
+pub fn start_scheduling() {
+    START_SCHEDULING.store(true, Ordering::SeqCst);
+}
+
+// Mindestens 'ticks' PIT-Ticks warten (Interrupts muessen an sein)
+fn wait_ticks(ticks: u64) {
+    let end = pit::get_systime() + ticks;
+    while pit::get_systime() < end {
+        cpu::pause();
+    }
+}
+
+// Parameter in die Kopie von 'ap_boot.asm' eintragen
+fn set_boot_param(param: *const u8, value: u64) {
+    let offset = param as usize - ptr::addr_of!(_ap_boot_start) as usize;
+    unsafe { ptr::write_volatile((AP_BOOT_ADDR + offset) as *mut u64, value) };
+}
+
+// AP mit 'apic_id' starten und warten, bis sie sich meldet
+fn start_ap(apic_id: u32) -> bool {
+    let cpu_nr = cpu_count();
+
+    // Startcode nach AP_BOOT_ADDR kopieren und Parameter eintragen
+    unsafe {
+        let start = ptr::addr_of!(_ap_boot_start);
+        let len = ptr::addr_of!(_ap_boot_end) as usize - start as usize;
+        ptr::copy_nonoverlapping(start, AP_BOOT_ADDR as *mut u8, len);
+    }
+    // Vom Heap, Thread-Stacks werden erst bei Seitenfehlern mit Frames hinterlegt
+    let stack = Box::into_raw(Box::new(stack::Stack::new(AP_STACK_SIZE))); // wird nie freigegeben
+    unsafe {
+        set_boot_param(ptr::addr_of!(_ap_boot_cr3), cpu::read_cr3());
+        set_boot_param(ptr::addr_of!(_ap_boot_stack), (*stack).end_of_stack() as u64);
+        set_boot_param(ptr::addr_of!(_ap_boot_entry), ap_entry as *const () as u64);
+        set_boot_param(ptr::addr_of!(_ap_boot_cpu), cpu_nr as u64);
+    }
+    CPU_NUMBERS[(apic_id & 0xff) as usize].store(cpu_nr as u8, Ordering::SeqCst);
+    AP_ONLINE.store(false, Ordering::SeqCst);
+
+    // INIT-SIPI-SIPI, der zweite SIPI nur, falls der erste nicht gereicht hat
+    lapic::send_init(apic_id);
+    wait_ticks(1);
+    for _ in 0..2 {
+        lapic::send_startup(apic_id, (AP_BOOT_ADDR >> 12) as u8);
+        wait_ticks(1);
+        if AP_ONLINE.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+
+    // bis zu einer Sekunde warten
+    let end = pit::get_systime() + 1000 / pit::TICK_MS;
+    while AP_ONLINE.load(Ordering::SeqCst) == false && pit::get_systime() < end {
+        cpu::pause();
+    }
+    if AP_ONLINE.load(Ordering::SeqCst) == false {
+        kprintln!("smp: cpu with apic_id {} did not start", apic_id);
+        return false;
+    }
+    CPU_COUNT.fetch_add(1, Ordering::SeqCst);
+    kprintln!("smp: cpu {} (apic_id {}) online", cpu_nr, apic_id);
+    true
+}
+
+// Eigene GDT mit TSS laden. Die Eintraege bis auf das TSS sind dieselben wie in 'boot.asm'.
+fn load_gdt(cpu_nr: usize) {
+    unsafe {
+        let per_cpu = ptr::addr_of_mut!(PER_CPU[cpu_nr]);
+        let gdt = ptr::addr_of_mut!((*per_cpu).gdt) as *mut u64;
+        let boot_gdt = ptr::addr_of!(_gdt);
+        for i in 0..6 {
+            *gdt.add(i) = *boot_gdt.add(i);
+        }
+
+        // TSS-Deskriptor (16 Bytes): Limit 0x67, Typ 0x89 (64-Bit-TSS, present), Basisadresse
+        let base = ptr::addr_of!((*per_cpu).tss) as u64;
+        *gdt.add(6) = 0x67 | ((base & 0xff_ffff) << 16) | (0x89 << 40) | (((base >> 24) & 0xff) << 56);
+        *gdt.add(7) = base >> 32;
+
+        let gdt_ptr = GdtPointer {
+            limit: (8 * 8 - 1) as u16,
+            base: gdt as u64,
+        };
+        asm!("lgdt [{}]", in(reg) &gdt_ptr, options(readonly, nostack, preserves_flags));
+
+        // CS ueber einen Far-Return neu laden, danach die Datensegmente
+        asm!(
+            "push {code}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov {tmp}, {data}",
+            "mov ds, {tmp:x}",
+            "mov es, {tmp:x}",
+            "mov fs, {tmp:x}",
+            "mov gs, {tmp:x}",
+            "mov ss, {tmp:x}",
+            code = in(reg) KERNEL_CODE_SELECTOR,
+            data = in(reg) KERNEL_DATA_SELECTOR,
+            tmp = out(reg) _,
+        );
+
+        asm!("ltr {0:x}", in(reg) TSS_SELECTOR, options(nostack, preserves_flags));
+    }
+}
+
+/**
+ Description: Set entry `n` (1..7) of the interrupt stack table in the TSS
+              of the calling cpu, see `pagefault::init`
+*/
+pub fn set_ist(n: usize, rsp: u64) {
+    assert!((1..=7).contains(&n), "smp::set_ist: invalid index");
+    unsafe {
+        let ist = ptr::addr_of_mut!((*tss()).ist) as *mut u64;
+        ptr::write_unaligned(ist.add(n - 1), rsp);
+    }
+}
+
+/**
+ Description: Get `rsp0` from the TSS of the calling cpu, the end of the
+              kernel stack of the running thread, see `pagefault::int_pf`
+*/
+pub fn rsp0() -> u64 {
+    unsafe { ptr::read_unaligned(ptr::addr_of!((*tss()).rsp) as *const u64) }
+}
+
+// TSS der aufrufenden CPU. Wie '_tss_set_rsp0' die Basisadresse aus dem
+// TSS-Deskriptor der aktiven GDT holen.
+fn tss() -> *mut Tss {
+    let mut gdt_ptr = GdtPointer { limit: 0, base: 0 };
+    unsafe {
+        asm!("sgdt [{}]", in(reg) ptr::addr_of_mut!(gdt_ptr), options(nostack, preserves_flags));
+        let desc = (gdt_ptr.base + TSS_SELECTOR as u64) as *const u64;
+        let low = *desc;
+        let high = *desc.add(1);
+        let base = ((low >> 16) & 0xff_ffff) | (((low >> 56) & 0xff) << 24) | (high << 32);
+        base as *mut Tss
+    }
+}
+
+//
+// Einstiegsfunktion einer AP, wird von 'ap_boot.asm' auf dem Stack aus
+// 'start_ap' gerufen. Die Interrupts sind gesperrt.
+//
+extern "C" fn ap_entry(cpu_nr: u64) -> ! {
+    let cpu_nr = cpu_nr as usize;
+    load_gdt(cpu_nr);
+    unsafe { _load_idt() };
+    lapic::init_ap();
+    paging::init();
+    pagefault::init(cpu_nr);
+
+    AP_ONLINE.store(true, Ordering::SeqCst);
+    while START_SCHEDULING.load(Ordering::SeqCst) == false {
+        cpu::pause();
+    }
+
+    // Der Idle-Thread dieser CPU wurde von 'startup' angelegt
+    scheduler::Scheduler::schedule();
+    panic!("ap_entry: scheduler returned on cpu {}", cpu_nr);
+}
@@ -0,0 +1,92 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: syscall                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: System calls of user threads with 'int 0x80'. The number of the ║
+   ║         call is passed in rax, up to three parameters in rdi, rsi and   ║
+   ║         rdx, the result is returned in rax. 'interrupts.asm' calls      ║
+   ║         'int_syscall' directly (not 'int_disp'), it runs on the kernel  ║
+   ║         stack of the calling thread and may block it.                   ║
+   ║                                                                         ║
+   ║         The functions for calling them in ring 3 are in 'usrlib'.       ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::slice;
+use core::str;
+
+use crate::kernel::cpu;
+use crate::kernel::interrupts::intdispatcher::{self, INT_VEC_SYSCALL};
+use crate::kernel::paging::{self, PAGE_SIZE};
+use crate::kernel::threads::scheduler::{self, Scheduler};
+
+// Nummern der Systemaufrufe
+pub const SYSNO_EXIT: u64 = 0; // (code), kehrt nicht zurueck
+pub const SYSNO_YIELD: u64 = 1; // ()
+pub const SYSNO_SLEEP_MS: u64 = 2; // (ms)
+pub const SYSNO_PRINT: u64 = 3; // (Adresse, Laenge) eines UTF-8-Strings
+pub const SYSNO_GET_TID: u64 = 4; // () -> tid
+
+// Ergebnis bei unbekannter Nummer oder ungueltigen Parametern
+pub const SYSCALL_ERROR: u64 = u64::MAX;
+
+/**
+ Description: Allow `int 0x80` in ring 3
+*/
+pub fn plugin() {
+    intdispatcher::allow_user(INT_VEC_SYSCALL);
+}
+
+/**
+ Description: System call dispatcher, called from `interrupts.asm` with
+              interrupts disabled.
+
+ Parameters: \
+    `number` see `SYSNO_*` \
+    `arg1` .. `arg3` parameters of the call
+
+ Return: \
+    result of the call, `SYSCALL_ERROR` on errors
+*/
+#[no_mangle]
+pub extern "C" fn int_syscall(number: u64, arg1: u64, arg2: u64, _arg3: u64) -> u64 {
+    // Der Thread kam aus dem Ring 3 und haelt keine Kernel-Locks
+    cpu::enable_int();
+    match number {
+        SYSNO_EXIT => Scheduler::exit_with_code(arg1 as i32),
+        SYSNO_YIELD => {
+            Scheduler::yield_cpu();
+            0
+        }
+        SYSNO_SLEEP_MS => {
+            Scheduler::sleep_ms(arg1);
+            0
+        }
+        SYSNO_PRINT => sys_print(arg1, arg2),
+        SYSNO_GET_TID => scheduler::get_active_tid() as u64,
+        _ => SYSCALL_ERROR,
+    }
+}
+
+// String '[addr; addr + len)' ausgeben, er muss im Ring 3 lesbar sein
+fn sys_print(addr: u64, len: u64) -> u64 {
+    let end = match addr.checked_add(len) {
+        Some(e) => e,
+        None => return SYSCALL_ERROR,
+    };
+    let pml4 = paging::active_pml4();
+    let mut page = addr & !(PAGE_SIZE - 1);
+    while page < end {
+        if paging::is_user_in(pml4, page) == false {
+            return SYSCALL_ERROR;
+        }
+        page += PAGE_SIZE;
+    }
+
+    let bytes = unsafe { slice::from_raw_parts(addr as *const u8, len as usize) };
+    match str::from_utf8(bytes) {
+        Ok(s) => {
+            print!("{}", s);
+            len
+        }
+        Err(_) => SYSCALL_ERROR,
+    }
+}
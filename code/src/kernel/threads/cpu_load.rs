@@ -0,0 +1,160 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: cpu_load                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Accounting of idle and busy ticks. The timer ISR of each cpu    ║
+   ║         (PIT or local APIC timer) calls `tick` for each interrupt. For  ║
+   ║         each second the busy ticks are stored in a ring buffer per cpu  ║
+   ║         to compute the cpu load of the last 1, 5 and 15 seconds.        ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::fmt;
+
+use crate::consts;
+use crate::devices::pit;
+use crate::kernel::cpu;
+use crate::kernel::smp;
+use crate::mylib::spinlock::Spinlock;
+
+const TICKS_PER_SECOND: u64 = 1000 / pit::TICK_MS;
+const HISTORY_SECONDS: usize = 15;
+
+/**
+ Description: Cpu load in percent, averaged over the last 1, 5 and 15 seconds
+*/
+#[derive(Clone, Copy)]
+pub struct LoadAvg {
+    pub avg_1s: u64,
+    pub avg_5s: u64,
+    pub avg_15s: u64,
+}
+
+impl fmt::Display for LoadAvg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "load: {}% {}% {}%", self.avg_1s, self.avg_5s, self.avg_15s)
+    }
+}
+
+struct LoadStats {
+    idle_ticks: u64,                  // seit dem Booten
+    busy_ticks: u64,                  // seit dem Booten
+    second_busy: u64,                 // Busy-Ticks in der laufenden Sekunde
+    second_ticks: u64,                // Ticks in der laufenden Sekunde
+    history: [u64; HISTORY_SECONDS],  // Busy-Ticks der letzten Sekunden (Ringpuffer)
+    next: usize,                      // naechster Eintrag in 'history'
+    filled: usize,                    // Anzahl gueltiger Eintraege in 'history'
+}
+
+impl LoadStats {
+    const fn new() -> LoadStats {
+        LoadStats {
+            idle_ticks: 0,
+            busy_ticks: 0,
+            second_busy: 0,
+            second_ticks: 0,
+            history: [0; HISTORY_SECONDS],
+            next: 0,
+            filled: 0,
+        }
+    }
+}
+
+// Je CPU eigene Zaehler
+static STATS: [Spinlock<LoadStats>; consts::MAX_CPUS] =
+    [const { Spinlock::new(LoadStats::new()) }; consts::MAX_CPUS];
+
+/**
+ Description: Account one timer tick of the calling cpu. Called from the
+              timer ISRs only.
+
+ Parameters: \
+    `idle` `true` if the idle thread was running during the tick
+*/
+pub fn tick(idle: bool) {
+    let mut stats = STATS[smp::cpu_id()].lock();
+    if idle {
+        stats.idle_ticks += 1;
+    } else {
+        stats.busy_ticks += 1;
+        stats.second_busy += 1;
+    }
+
+    stats.second_ticks += 1;
+    if stats.second_ticks == TICKS_PER_SECOND {
+        let next = stats.next;
+        stats.history[next] = stats.second_busy;
+        stats.next = (next + 1) % HISTORY_SECONDS;
+        if stats.filled < HISTORY_SECONDS {
+            stats.filled += 1;
+        }
+        stats.second_busy = 0;
+        stats.second_ticks = 0;
+    }
+}
+
+// Durchschnittliche Last der letzten 'seconds' Sekunden in Prozent
+fn average(stats: &LoadStats, seconds: usize) -> u64 {
+    let n = seconds.min(stats.filled);
+    if n == 0 {
+        return 0;
+    }
+    let mut busy = 0;
+    for i in 1..=n {
+        busy += stats.history[(stats.next + HISTORY_SECONDS - i) % HISTORY_SECONDS];
+    }
+    busy * 100 / (n as u64 * TICKS_PER_SECOND)
+}
+
+/**
+ Description: Get the load of cpu `cpu` of the last 1, 5 and 15 seconds in
+              percent. Only full seconds are considered.
+*/
+pub fn get_cpu_load(cpu: usize) -> LoadAvg {
+    let was_enabled = cpu::disable_int_nested();
+    let load = {
+        let stats = STATS[cpu].lock();
+        LoadAvg {
+            avg_1s: average(&stats, 1),
+            avg_5s: average(&stats, 5),
+            avg_15s: average(&stats, 15),
+        }
+    };
+    cpu::enable_int_nested(was_enabled);
+    load
+}
+
+/**
+ Description: Get the load of the last 1, 5 and 15 seconds in percent,
+              averaged over all running cpus, see `get_cpu_load`.
+*/
+pub fn get_load() -> LoadAvg {
+    let count = smp::cpu_count() as u64;
+    let mut sum = LoadAvg { avg_1s: 0, avg_5s: 0, avg_15s: 0 };
+    for cpu in 0..smp::cpu_count() {
+        let load = get_cpu_load(cpu);
+        sum.avg_1s += load.avg_1s;
+        sum.avg_5s += load.avg_5s;
+        sum.avg_15s += load.avg_15s;
+    }
+    LoadAvg {
+        avg_1s: sum.avg_1s / count,
+        avg_5s: sum.avg_5s / count,
+        avg_15s: sum.avg_15s / count,
+    }
+}
+
+/**
+ Description: Get the number of idle and busy ticks since booting, summed
+              over all cpus
+
+ Return: \
+    `(idle, busy)` ticks
+*/
+pub fn get_ticks() -> (u64, u64) {
+    let was_enabled = cpu::disable_int_nested();
+    let ticks = STATS.iter().fold((0, 0), |(idle, busy), s| {
+        let stats = s.lock();
+        (idle + stats.idle_ticks, busy + stats.busy_ticks)
+    });
+    cpu::enable_int_nested(was_enabled);
+    ticks
+}
@@ -0,0 +1,119 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: fair_share                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Fair share scheduling similar to Linux CFS. Each thread has a   ║
+   ║         virtual runtime which grows with each tick it runs, the faster  ║
+   ║         the lower its priority. The ready thread with the smallest      ║
+   ║         virtual runtime runs next.                                      ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::kernel::threads::policy::SchedulingPolicy;
+use crate::kernel::threads::thread::Thread;
+
+// Virtuelle Laufzeit fuer einen Tick mit Prioritaet 0, halbiert sich je Prioritaetsstufe
+const TICK_VRUNTIME: u64 = 1024;
+
+// So viele Ticks laeuft ein Thread mindestens, bevor er verdraengt wird
+const MIN_SLICE_TICKS: u64 = 2;
+
+// Geweckte Threads duerfen um so viel hinter 'min_vruntime' liegen
+const WAKEUP_BONUS: u64 = TICK_VRUNTIME;
+
+#[allow(clippy::vec_box)] // Threads duerfen nicht verschoben werden, siehe 'Scheduler'
+pub struct FairShare {
+    ready: Vec<Box<Thread>>, // unsortiert, 'pick_next' sucht das Minimum
+    min_vruntime: u64,       // waechst monoton, Startwert fuer neue Threads
+    slice_ticks: u64,        // Ticks des aktiven Threads seit 'pick_next'
+}
+
+impl FairShare {
+    pub const fn new() -> Self {
+        FairShare {
+            ready: Vec::new(),
+            min_vruntime: 0,
+            slice_ticks: 0,
+        }
+    }
+
+    // Index des bereiten Threads mit der kleinsten virtuellen Laufzeit, der
+    // 'runnable' erfuellt (bei Gleichstand der am laengsten wartende)
+    fn leftmost(&self, runnable: &dyn Fn(&Thread) -> bool) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (i, t) in self.ready.iter().enumerate().filter(|(_, t)| runnable(t)) {
+            match best {
+                Some(b) if self.ready[b].get_vruntime() <= t.get_vruntime() => {}
+                _ => best = Some(i),
+            }
+        }
+        best
+    }
+}
+
+impl SchedulingPolicy for FairShare {
+    fn name(&self) -> &'static str {
+        "fair"
+    }
+
+    fn enqueue(&mut self, that: Box<Thread>) {
+        self.ready.push(that);
+    }
+
+    fn pick_next(&mut self, runnable: &dyn Fn(&Thread) -> bool) -> Option<Box<Thread>> {
+        let next = self.ready.remove(self.leftmost(runnable)?);
+        if next.get_vruntime() > self.min_vruntime {
+            self.min_vruntime = next.get_vruntime();
+        }
+        self.slice_ticks = 0;
+        Some(next)
+    }
+
+    fn len(&self) -> usize {
+        self.ready.len()
+    }
+
+    fn remove(&mut self, tid: usize) -> Option<Box<Thread>> {
+        let pos = self.ready.iter().position(|t| Thread::get_tid(t.as_ref()) == tid)?;
+        Some(self.ready.remove(pos))
+    }
+
+    // Laufzeit gewichtet nach Prioritaet verbuchen, verdraengt wird nach
+    // der Mindestzeitscheibe, falls ein anderer Thread weniger bekommen hat
+    fn on_tick(&mut self, active: &mut Thread) -> bool {
+        let delta = TICK_VRUNTIME >> active.get_priority();
+        active.set_vruntime(active.get_vruntime() + delta);
+        self.slice_ticks += 1;
+        if self.slice_ticks < MIN_SLICE_TICKS {
+            return false;
+        }
+        match self.leftmost(&|_| true) {
+            Some(i) => self.ready[i].get_vruntime() < active.get_vruntime(),
+            None => false,
+        }
+    }
+
+    fn on_block(&mut self, _active: &mut Thread) {
+        self.slice_ticks = 0;
+    }
+
+    // Ein lange blockierter Thread darf die anderen nicht verhungern lassen,
+    // er wird knapp vor den Thread mit der kleinsten Laufzeit gesetzt
+    fn on_wakeup(&mut self, that: &mut Thread) {
+        let floor = self.min_vruntime.saturating_sub(WAKEUP_BONUS);
+        if that.get_vruntime() < floor {
+            that.set_vruntime(floor);
+        }
+    }
+
+    // Die virtuelle Laufzeit zaehlt relativ zu 'min_vruntime' der CPU, sonst
+    // haette ein Thread von einer CPU mit kleinerer 'min_vruntime' lange Vorrang
+    fn on_migrate_out(&mut self, that: &mut Thread) {
+        that.set_vruntime(that.get_vruntime().saturating_sub(self.min_vruntime));
+    }
+
+    fn on_migrate_in(&mut self, that: &mut Thread) {
+        that.set_vruntime(that.get_vruntime() + self.min_vruntime);
+    }
+}
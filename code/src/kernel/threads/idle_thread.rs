@@ -1,16 +1,13 @@
+use crate::kernel::cpu;
 use crate::kernel::threads::scheduler;
 
+/**
+ Description: Entry function of the idle thread. It runs only if no other
+              thread is ready and halts the cpu until the next interrupt.
+              The PIT ISR switches to another thread as soon as one is ready.
+*/
 pub extern "C" fn idle_thread_entry() {
     scheduler::set_initialized();
-    loop {
-        print!("I");
-
-        let mut x: u64 = 0;
-        loop {
-            x = x + 1;
-            if x > 100000000 {
-                break;
-            }
-        }
-    }
+    cpu::enable_int();
+    cpu::halt();
 }
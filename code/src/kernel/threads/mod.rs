@@ -1,5 +1,10 @@
-
 pub mod thread;
 pub mod scheduler;
+pub mod policy;
+pub mod round_robin;
+pub mod fair_share;
+pub mod realtime;
 pub mod idle_thread;
+pub mod reaper_thread;
+pub mod cpu_load;
 pub mod stack;
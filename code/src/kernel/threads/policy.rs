@@ -0,0 +1,104 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: policy                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Interface between the scheduler and a scheduling policy. The    ║
+   ║         scheduler does the bookkeeping (blocking, sleeping, exit) and   ║
+   ║         asks the policy which ready thread runs next and when.          ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+
+use crate::kernel::threads::fair_share::FairShare;
+use crate::kernel::threads::round_robin::RoundRobin;
+use crate::kernel::threads::thread::Thread;
+
+/**
+ Description: A scheduling policy owns the ready threads. All functions are
+              called by the scheduler with the `SCHEDULER` lock held and
+              interrupts disabled. Each cpu has its own policy object. The idle thread and real-time threads
+              (see `realtime`) are never passed to a policy.
+*/
+pub trait SchedulingPolicy {
+    /**
+     Description: Short name of the policy, e.g. for `ps`
+    */
+    fn name(&self) -> &'static str;
+
+    /**
+     Description: `that` is ready to run (new, preempted, yielded or woken up)
+    */
+    fn enqueue(&mut self, that: Box<Thread>);
+
+    /**
+     Description: Remove and return the thread which should run next. Only
+                  threads satisfying `runnable` may be returned (they may be
+                  bound to other cpus or still running on another cpu).
+
+     Return: \
+        `None` if no such thread is ready (the scheduler then tries the other
+        cpus and finally runs the idle thread)
+    */
+    fn pick_next(&mut self, runnable: &dyn Fn(&Thread) -> bool) -> Option<Box<Thread>>;
+
+    /**
+     Description: Number of ready threads, used for load balancing
+    */
+    fn len(&self) -> usize;
+
+    /**
+     Description: Remove the ready thread `tid`, e.g. if it is killed or its
+                  priority changes.
+    */
+    fn remove(&mut self, tid: usize) -> Option<Box<Thread>>;
+
+    /**
+     Description: Called by the PIT ISR for each tick, while `active` runs.
+
+     Return: \
+        `true` if `active` should be preempted
+    */
+    fn on_tick(&mut self, active: &mut Thread) -> bool;
+
+    /**
+     Description: `active` gives up the cpu without being ready again
+                  (it blocks, sleeps or terminates).
+    */
+    fn on_block(&mut self, active: &mut Thread);
+
+    /**
+     Description: `that` becomes ready after being blocked or sleeping,
+                  also called for a new thread. `enqueue` follows.
+    */
+    fn on_wakeup(&mut self, that: &mut Thread);
+
+    /**
+     Description: The ready thread `that` moves from this policy to the
+                  policy of another cpu (load balancing). State relative
+                  to this cpu, e.g. a virtual runtime, is made independent
+                  of it. `on_migrate_in` of the other policy follows.
+    */
+    fn on_migrate_out(&mut self, _that: &mut Thread) {}
+
+    /**
+     Description: The ready thread `that` comes from the policy of another
+                  cpu, see `on_migrate_out`. `enqueue` follows, unless the
+                  thread runs at once (stolen by an idle cpu).
+    */
+    fn on_migrate_in(&mut self, _that: &mut Thread) {}
+}
+
+/**
+ Description: Create a policy by name, used to select the policy at boot
+              (`sched=<name>` on the kernel command line). \
+              `rr`: priority round-robin with aging, `fair`: fair share
+
+ Return: \
+    `None` if there is no policy `name`
+*/
+pub fn from_name(name: &str) -> Option<Box<dyn SchedulingPolicy>> {
+    match name {
+        "rr" => Some(Box::new(RoundRobin::new())),
+        "fair" => Some(Box::new(FairShare::new())),
+        _ => None,
+    }
+}
@@ -0,0 +1,277 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: realtime                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Periodic real-time threads, scheduled earliest deadline first   ║
+   ║         (EDF) ahead of all other threads. In each period a thread may   ║
+   ║         run for its budget, then it waits for the next period. New      ║
+   ║         threads are only admitted if the task set stays schedulable.    ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::consts;
+use crate::kernel::threads::thread::{Thread, ThreadState};
+
+/**
+ Description: Parameters and state of the current job of a real-time thread.
+              All times are given in PIT ticks.
+*/
+#[derive(Clone, Copy)]
+pub struct RealTime {
+    period: u64,
+    budget: u64,      // Rechenzeit je Periode
+    deadline: u64,    // relativ zum Beginn der Periode
+    release: u64,     // Beginn der aktuellen Periode
+    budget_left: u64,
+    job_done: bool,   // 'wait_next_period' wurde in dieser Periode gerufen
+    missed: bool,     // Deadline-Verletzung der aktuellen Periode wurde gezaehlt
+    jobs: u64,        // Anzahl der begonnenen Perioden
+    deadline_misses: u64,
+}
+
+impl RealTime {
+    /**
+     Description: Create the parameters, `0 < budget <= deadline <= period` is required
+    */
+    pub fn new(period: u64, budget: u64, deadline: u64) -> RealTime {
+        assert!(
+            budget > 0 && budget <= deadline && deadline <= period,
+            "RealTime::new: invalid parameters"
+        );
+        RealTime {
+            period,
+            budget,
+            deadline,
+            release: 0,
+            budget_left: 0,
+            job_done: true,
+            missed: false,
+            jobs: 0,
+            deadline_misses: 0,
+        }
+    }
+
+    // Auslastung in Millionstel (Dichte 'budget / deadline', fuer 'deadline <= period' hinreichend)
+    fn utilization(&self) -> u64 {
+        self.budget * 1_000_000 / self.deadline
+    }
+
+    // Neue Periode beginnen
+    fn start_job(&mut self, release: u64) {
+        self.release = release;
+        self.budget_left = self.budget;
+        self.job_done = false;
+        self.missed = false;
+        self.jobs += 1;
+    }
+
+    /**
+     Description: Start a new period at `now`, used when a suspended thread
+                  is resumed (the periods in between are skipped)
+    */
+    pub fn restart(&mut self, now: u64) {
+        self.start_job(now);
+    }
+
+    // Darf der Thread in der aktuellen Periode noch laufen?
+    fn is_runnable(&self) -> bool {
+        self.job_done == false && self.budget_left > 0
+    }
+
+    fn next_release(&self) -> u64 {
+        self.release + self.period
+    }
+
+    pub fn get_abs_deadline(&self) -> u64 {
+        self.release + self.deadline
+    }
+
+    /**
+     Description: Account one tick of cpu time.
+
+     Return: \
+        `true` if the thread may continue in this period
+    */
+    pub fn charge_tick(&mut self) -> bool {
+        self.budget_left = self.budget_left.saturating_sub(1);
+        self.is_runnable()
+    }
+
+    /**
+     Description: Count a deadline miss, if the current job has not finished
+                  in time. Called for each tick.
+    */
+    pub fn check_deadline(&mut self, now: u64) {
+        if self.job_done == false && self.missed == false && now > self.get_abs_deadline() {
+            self.missed = true;
+            self.deadline_misses += 1;
+        }
+    }
+
+    // Aufgabe der aktuellen Periode ist erledigt
+    fn complete_job(&mut self, now: u64) {
+        self.check_deadline(now);
+        self.job_done = true;
+    }
+
+    pub fn get_period(&self) -> u64 {
+        self.period
+    }
+
+    pub fn get_budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn get_deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    pub fn get_jobs(&self) -> u64 {
+        self.jobs
+    }
+
+    pub fn get_deadline_misses(&self) -> u64 {
+        self.deadline_misses
+    }
+}
+
+impl fmt::Display for RealTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>6} {:>6} {:>8} {:>8} {:>8}",
+            self.period, self.budget, self.deadline, self.jobs, self.deadline_misses
+        )
+    }
+}
+
+/**
+ Description: Ready and waiting real-time threads, used by the scheduler.
+              All functions are called with the `SCHEDULER` lock held.
+*/
+#[allow(clippy::vec_box)] // Threads duerfen nicht verschoben werden, siehe 'Scheduler'
+pub struct Edf {
+    ready: Vec<Box<Thread>>,   // koennen in dieser Periode noch laufen
+    waiting: Vec<Box<Thread>>, // warten auf den Beginn der naechsten Periode
+    utilization: u64,          // Summe der Auslastung aller zugelassenen Threads (Millionstel)
+}
+
+impl Edf {
+    pub const fn new() -> Self {
+        Edf {
+            ready: Vec::new(),
+            waiting: Vec::new(),
+            utilization: 0,
+        }
+    }
+
+    /**
+     Description: Admission control. The task set stays schedulable by EDF as
+                  long as the sum of `budget / deadline` does not exceed
+                  `RT_MAX_UTILIZATION` percent. The rest is left for normal threads.
+
+     Return: \
+        `true` if the thread has been admitted, its first period starts at `now`
+    */
+    pub fn admit(&mut self, rt: &mut RealTime, now: u64) -> bool {
+        let u = rt.utilization();
+        if self.utilization + u > consts::RT_MAX_UTILIZATION * 10_000 {
+            return false;
+        }
+        self.utilization += u;
+        rt.start_job(now);
+        true
+    }
+
+    // Auslastung eines beendeten Threads freigeben
+    pub fn release_share(&mut self, rt: &RealTime) {
+        self.utilization -= rt.utilization();
+    }
+
+    /**
+     Description: Insert `that` as ready thread or, if its job is done or its
+                  budget is used up, as thread waiting for the next period.
+    */
+    pub fn enqueue(&mut self, mut that: Box<Thread>) {
+        if that.get_realtime().unwrap().is_runnable() {
+            that.set_state(ThreadState::Ready);
+            self.ready.push(that);
+        } else {
+            that.set_state(ThreadState::Sleeping);
+            self.waiting.push(that);
+        }
+    }
+
+    // Index des bereiten Threads mit der fruehesten Deadline, der 'runnable' erfuellt
+    fn earliest(&self, runnable: &dyn Fn(&Thread) -> bool) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (i, t) in self.ready.iter().enumerate().filter(|(_, t)| runnable(t)) {
+            let d = t.get_realtime().unwrap().get_abs_deadline();
+            match best {
+                Some(b) if self.ready[b].get_realtime().unwrap().get_abs_deadline() <= d => {}
+                _ => best = Some(i),
+            }
+        }
+        best
+    }
+
+    /**
+     Description: Absolute deadline of the most urgent ready thread which
+                  satisfies `runnable` (e.g. may run on a certain cpu)
+    */
+    pub fn earliest_deadline(&self, runnable: &dyn Fn(&Thread) -> bool) -> Option<u64> {
+        self.earliest(runnable).map(|i| self.ready[i].get_realtime().unwrap().get_abs_deadline())
+    }
+
+    /**
+     Description: Remove and return the ready thread with the earliest deadline,
+                  only threads satisfying `runnable` are considered
+    */
+    pub fn pick_next(&mut self, runnable: &dyn Fn(&Thread) -> bool) -> Option<Box<Thread>> {
+        let i = self.earliest(runnable)?;
+        Some(self.ready.remove(i))
+    }
+
+    pub fn remove(&mut self, tid: usize) -> Option<Box<Thread>> {
+        if let Some(pos) = self.ready.iter().position(|t| Thread::get_tid(t.as_ref()) == tid) {
+            return Some(self.ready.remove(pos));
+        }
+        let pos = self.waiting.iter().position(|t| Thread::get_tid(t.as_ref()) == tid)?;
+        Some(self.waiting.remove(pos))
+    }
+
+    /**
+     Description: Start the next period of all waiting threads whose period
+                  has begun. Called for each tick.
+    */
+    pub fn release_jobs(&mut self, now: u64) {
+        let mut i = 0;
+        while i < self.waiting.len() {
+            let rt = self.waiting[i].get_realtime_mut().unwrap();
+            if rt.next_release() <= now {
+                // verpasste Perioden werden uebersprungen
+                let mut release = rt.next_release();
+                while release + rt.period <= now {
+                    release += rt.period;
+                }
+                rt.start_job(release);
+                let mut that = self.waiting.remove(i);
+                that.set_state(ThreadState::Ready);
+                self.ready.push(that);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /**
+     Description: The active thread `that` finished its job of this period.
+                  It is put into the waiting threads.
+    */
+    pub fn complete(&mut self, mut that: Box<Thread>, now: u64) {
+        that.get_realtime_mut().unwrap().complete_job(now);
+        self.enqueue(that);
+    }
+}
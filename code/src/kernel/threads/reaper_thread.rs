@@ -0,0 +1,13 @@
+use crate::kernel::threads::scheduler;
+
+/**
+ Description: Entry function of the reaper thread. Frees the memory
+              (thread object and stacks) of terminated threads and
+              blocks as long as there is nothing to do.
+*/
+pub extern "C" fn reaper_thread_entry() {
+    loop {
+        scheduler::reap();
+        scheduler::Scheduler::wait_for_dead();
+    }
+}
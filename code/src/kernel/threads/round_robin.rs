@@ -0,0 +1,102 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: round_robin                                                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Priority scheduling with one round-robin ready queue per        ║
+   ║         priority level. Threads waiting too long are promoted (aging).  ║
+   ║         Each thread runs for one tick before it is preempted.           ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+
+use crate::consts;
+use crate::devices::pit;
+use crate::kernel::threads::policy::SchedulingPolicy;
+use crate::kernel::threads::thread::Thread;
+use crate::mylib::queue::Queue;
+
+// Leere Ready-Queue, noetig fuer die Initialisierung des Arrays in 'new'
+const EMPTY_QUEUE: Queue<Box<Thread>> = Queue::new();
+
+pub struct RoundRobin {
+    ready_queues: [Queue<Box<Thread>>; consts::PRIORITY_LEVELS], // eine Queue je Prioritaet
+}
+
+impl RoundRobin {
+    pub const fn new() -> Self {
+        RoundRobin {
+            ready_queues: [EMPTY_QUEUE; consts::PRIORITY_LEVELS],
+        }
+    }
+
+    /**
+        Description: Aging. Each thread which waits at least `AGING_TICKS` in its ready queue \
+                     is moved one level up. As the queues are FIFO, only the heads have to be checked. \
+                     Levels are processed top-down, so a thread climbs at most one level per call.
+    */
+    fn age(&mut self) {
+        let now = pit::get_systime();
+        for prio in (0..consts::PRIORITY_LEVELS - 1).rev() {
+            loop {
+                let aged = match self.ready_queues[prio].peek() {
+                    Some(t) => now.saturating_sub(t.get_ready_since()) >= consts::AGING_TICKS,
+                    None => false,
+                };
+                if aged == false {
+                    break;
+                }
+                let mut t = self.ready_queues[prio].dequeue().unwrap();
+                t.set_ready_since(now);
+                self.ready_queues[prio + 1].enqueue(t);
+            }
+        }
+    }
+}
+
+impl SchedulingPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "rr"
+    }
+
+    // Am Ende der Queue der Basis-Prioritaet eintragen, eine durch Aging
+    // gewonnene Prioritaet geht dabei verloren
+    fn enqueue(&mut self, mut that: Box<Thread>) {
+        that.set_ready_since(pit::get_systime());
+        let prio = that.get_priority();
+        self.ready_queues[prio].enqueue(that);
+    }
+
+    // Erster lauffaehiger Thread der hoechsten Queue, die einen solchen enthaelt
+    fn pick_next(&mut self, runnable: &dyn Fn(&Thread) -> bool) -> Option<Box<Thread>> {
+        for prio in (0..consts::PRIORITY_LEVELS).rev() {
+            let next = self.ready_queues[prio].remove_first(|t| runnable(t));
+            if next.is_some() {
+                return next;
+            }
+        }
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.ready_queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn remove(&mut self, tid: usize) -> Option<Box<Thread>> {
+        for prio in 0..consts::PRIORITY_LEVELS {
+            let that = self.ready_queues[prio].remove_first(|t| Thread::get_tid(t.as_ref()) == tid);
+            if that.is_some() {
+                return that;
+            }
+        }
+        None
+    }
+
+    // Zeitscheibe ist ein Tick
+    fn on_tick(&mut self, _active: &mut Thread) -> bool {
+        self.age();
+        true
+    }
+
+    fn on_block(&mut self, _active: &mut Thread) {}
+
+    fn on_wakeup(&mut self, _that: &mut Thread) {}
+}
@@ -1,22 +1,35 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: scheduler                                                       ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Descr.: A basic round-robin scheduler for cooperative threads.          ║
-   ║         No priorties supported.                                         ║
+   ║ Descr.: Thread bookkeeping (ready, blocked, sleeping and dead threads)  ║
+   ║         and thread switching. Which ready thread runs next is decided   ║
+   ║         by a `SchedulingPolicy`, selected at boot. Real-time threads    ║
+   ║         are scheduled EDF ahead of the policy, see `realtime`.          ║
+   ║         Each cpu has its own active thread, idle thread and policy      ║
+   ║         object (run queue). Ready threads are distributed by load and   ║
+   ║         affinity, an idle cpu steals threads from the other cpus.       ║
+   ║         Suspended threads are parked until they are resumed.            ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Autor:  Michael Schoettner, 15.05.2023                                  ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::any::Any;
 use core::ptr;
-use core::sync::atomic::AtomicUsize;
-use spin::Mutex;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
+use crate::consts;
 use crate::devices::cga;
+use crate::devices::pit;
 use crate::kernel::cpu;
+use crate::kernel::lockdep::SpinMutex;
+use crate::kernel::smp;
+use crate::kernel::threads::policy;
+use crate::kernel::threads::policy::SchedulingPolicy;
+use crate::kernel::threads::realtime;
+use crate::kernel::threads::round_robin::RoundRobin;
 use crate::kernel::threads::thread;
-use crate::mylib::queue;
 
 static THREAD_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
@@ -28,18 +41,43 @@ pub fn next_thread_id() -> usize {
     THREAD_ID_COUNTER.fetch_add(1, core::sync::atomic::Ordering::SeqCst)
 }
 
-pub static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+pub static SCHEDULER: SpinMutex<Scheduler> = SpinMutex::new("SCHEDULER", Scheduler::new());
+
+// Von ISRs deblockierte Threads, falls der Scheduler gerade gesperrt war
+// (verkettet ueber 'Thread::deferred_next', siehe 'deblock_from_isr')
+static DEFERRED: AtomicPtr<thread::Thread> = AtomicPtr::new(ptr::null_mut());
 
 /**
  Description: Return callers thread ID
 */
 pub fn get_active_tid() -> usize {
-    thread::Thread::get_tid(SCHEDULER.lock().active)
+    thread::Thread::get_tid(get_active())
+}
+
+/**
+ Description: Return the thread running on the calling cpu
+*/
+pub fn get_active() -> *mut thread::Thread {
+    SCHEDULER.lock().active()
 }
 
+// Noetig fuer die Initialisierung von 'policies' in 'new'
+const NO_POLICY: Option<Box<dyn SchedulingPolicy>> = None;
+
+// Threads werden auch ueber Zeiger referenziert ('threads', 'active', 'Thread::switch')
+// und duerfen nicht verschoben werden, wenn ein Vec waechst, daher 'Vec<Box<..>>'
+#[allow(clippy::vec_box)]
 pub struct Scheduler {
-    active: *mut thread::Thread,
-    ready_queue: queue::Queue<Box<thread::Thread>>, // auf die CPU wartende Threads
+    active: [*mut thread::Thread; consts::MAX_CPUS], // laufender Thread je CPU
+    policies: [Option<Box<dyn SchedulingPolicy>>; consts::MAX_CPUS], // bereite Threads je CPU, Standard ist 'RoundRobin'
+    edf: realtime::Edf,                // bereite und auf ihre Periode wartende Echtzeit-Threads
+    threads: Vec<*mut thread::Thread>, // alle lebenden Threads (auch blockierte)
+    sleep_queue: Vec<Box<thread::Thread>>, // schlafende Threads, absteigend nach Weckzeit sortiert
+    suspended: Vec<Box<thread::Thread>>, // angehaltene Threads, siehe 'suspend'
+    dead: Vec<Box<thread::Thread>>,    // beendete Threads, werden vom Reaper freigegeben
+    timeouts: Vec<*mut thread::Thread>, // blockierte Threads mit Timeout, siehe 'prepare_block_until'
+    reaper: *mut thread::Thread,       // wartender Reaper-Thread (sonst null)
+    idle: [*mut thread::Thread; consts::MAX_CPUS], // Idle-Thread je CPU, ist nie in einer Ready-Queue
     initialized: bool,
 }
 
@@ -52,17 +90,206 @@ impl Scheduler {
     */
     pub const fn new() -> Self {
         Scheduler {
-            active: ptr::null_mut(),
-            ready_queue: queue::Queue::new(),
+            active: [ptr::null_mut(); consts::MAX_CPUS],
+            policies: [NO_POLICY; consts::MAX_CPUS],
+            edf: realtime::Edf::new(),
+            threads: Vec::new(),
+            sleep_queue: Vec::new(),
+            suspended: Vec::new(),
+            dead: Vec::new(),
+            timeouts: Vec::new(),
+            reaper: ptr::null_mut(),
+            idle: [ptr::null_mut(); consts::MAX_CPUS],
             initialized: false,
         }
     }
 
+    // Policy der CPU 'cpu', beim ersten Zugriff ohne 'set_policy' Round-Robin
+    fn policy(&mut self, cpu: usize) -> &mut dyn SchedulingPolicy {
+        self.policies[cpu].get_or_insert_with(|| Box::new(RoundRobin::new())).as_mut()
+    }
+
+    // Aktiver Thread der aufrufenden CPU. Solange der Scheduler gesperrt ist,
+    // kann der Aufrufer nicht auf eine andere CPU wechseln.
+    fn active(&self) -> *mut thread::Thread {
+        self.active[smp::cpu_id()]
+    }
+
+    fn set_active(&mut self, that: *mut thread::Thread) {
+        self.active[smp::cpu_id()] = that;
+    }
+
+    // Anzahl bereiter und laufender Threads (ohne Idle-Thread) auf 'cpu'
+    fn load(&mut self, cpu: usize) -> usize {
+        let running = self.active[cpu].is_null() == false && self.active[cpu] != self.idle[cpu];
+        self.policy(cpu).len() + running as usize
+    }
+
     /**
-        Description: Check if we can switch from the current running thread to another one. \
+        Description: Choose the cpu whose policy gets the ready thread `that`. The cpu
+                     it ran on last is kept (warm cache) unless another allowed cpu has
+                     a load lower by more than one. The affinity mask always contains a
+                     running cpu, see `set_affinity`.
+    */
+    fn select_cpu(&mut self, that: &thread::Thread) -> usize {
+        let count = smp::cpu_count();
+        let mut best: Option<(usize, usize)> = None; // (CPU, Last)
+        for cpu in (0..count).filter(|c| that.may_run_on(*c)) {
+            let load = self.load(cpu);
+            if best.is_none_or(|(_, l)| load < l) {
+                best = Some((cpu, load));
+            }
+        }
+        match best {
+            // nicht erreichbar, Masken ohne laufende CPU werden abgelehnt
+            None => 0,
+            Some((cpu, load)) => {
+                let last = that.get_cpu();
+                if last < count && that.may_run_on(last) && self.load(last) <= load + 1 {
+                    last
+                } else {
+                    cpu
+                }
+            }
+        }
+    }
+
+    /**
+        Description: Hand `that` to the policy of a cpu as ready thread, real-time
+                     threads are handed to the EDF queue. Idle threads are skipped.
+                     A thread to be suspended is parked in `suspended` instead.
+    */
+    fn enqueue(&mut self, that: Box<thread::Thread>) {
+        self.enqueue_on(that, None);
+    }
+
+    // Wie 'enqueue', 'cpu' wurde evtl. schon mit 'select_cpu' gewaehlt
+    fn enqueue_on(&mut self, mut that: Box<thread::Thread>, cpu: Option<usize>) {
+        // Ein Idle-Thread wird nur gewaehlt, wenn kein anderer Thread bereit ist
+        if self.idle.contains(&that.get_raw_pointer()) {
+            let _ = Box::into_raw(that);
+            return;
+        }
+        if that.is_suspended() {
+            that.set_state(thread::ThreadState::Suspended);
+            self.suspended.push(that);
+            return;
+        }
+        if that.get_realtime().is_some() {
+            self.edf.enqueue(that);
+            return;
+        }
+        that.set_state(thread::ThreadState::Ready);
+        let cpu = match cpu {
+            Some(c) => c,
+            None => self.select_cpu(&that),
+        };
+        self.migrate(that.as_mut(), cpu);
+        self.policy(cpu).enqueue(that);
+    }
+
+    /**
+        Description: Make the new, deblocked or woken up thread `that` ready.
+    */
+    fn wakeup(&mut self, mut that: Box<thread::Thread>) {
+        self.cancel_timeout(that.get_raw_pointer());
+        let mut cpu = None;
+        if that.get_realtime().is_none() && self.idle.contains(&that.get_raw_pointer()) == false {
+            let c = self.select_cpu(&that);
+            self.migrate(that.as_mut(), c);
+            self.policy(c).on_wakeup(that.as_mut());
+            cpu = Some(c);
+        }
+        self.enqueue_on(that, cpu);
+    }
+
+    // Der bereite Thread 'that' wechselt zur Policy von 'cpu', falls er zuletzt
+    // zu einer anderen CPU gehoerte (z.B. Fair-Share: virtuelle Laufzeit umrechnen)
+    fn migrate(&mut self, that: &mut thread::Thread, cpu: usize) {
+        let from = that.get_cpu();
+        if from != cpu {
+            self.policy(from).on_migrate_out(that);
+            self.policy(cpu).on_migrate_in(that);
+            that.set_cpu(cpu);
+        }
+    }
+
+    // Aktiver Thread gibt die CPU ab, ohne bereit zu bleiben
+    fn block_active(&mut self) {
+        let cpu = smp::cpu_id();
+        let cur = self.active[cpu];
+        if thread::Thread::is_realtime(cur) == false {
+            self.policy(cpu).on_block(unsafe { &mut *cur });
+        }
+    }
+
+    // Bereiten Thread 'tid' aushaengen (Echtzeit-Thread oder bei einer Policy)
+    fn remove_ready(&mut self, tid: usize) -> Option<Box<thread::Thread>> {
+        match self.edf.remove(tid) {
+            Some(t) => Some(t),
+            None => self.policies.iter_mut().flatten().find_map(|p| p.remove(tid)),
+        }
+    }
+
+    /**
+        Description: Remove and return the next thread for `cpu`: the real-time thread
+                     with the earliest deadline, otherwise the thread chosen by the
+                     policy of `cpu`. If it has no thread, one is stolen from another
+                     cpu. If no thread is ready the idle thread of `cpu` is returned. \
+                     Threads not allowed on `cpu` and threads whose stack is still in
+                     use by another cpu (see `Thread::is_on_cpu`) are skipped.
+    */
+    fn dequeue_next(&mut self, cpu: usize) -> Option<Box<thread::Thread>> {
+        let me = self.active[cpu] as *const thread::Thread;
+        let runnable = move |t: &thread::Thread| {
+            t.may_run_on(cpu) && (t.is_on_cpu() == false || ptr::eq(t, me))
+        };
+        let mut next = self.edf.pick_next(&runnable);
+        if next.is_none() {
+            next = self.policy(cpu).pick_next(&runnable);
+        }
+        if next.is_none() {
+            next = self.steal(cpu, &runnable);
+        }
+        if next.is_none() && self.idle[cpu].is_null() == false {
+            next = Some(unsafe { Box::from_raw(self.idle[cpu]) });
+        }
+        // der gelieferte Thread wird vom Aufrufer immer zum aktiven Thread
+        if let Some(t) = next.as_mut() {
+            t.set_state(thread::ThreadState::Running);
+            t.set_on_cpu(cpu);
+        }
+        next
+    }
+
+    // Lastausgleich: bereiten Thread einer anderen CPU uebernehmen, die CPU mit
+    // den meisten bereiten Threads zuerst (ohne Heap, wir sind evtl. in einer ISR)
+    fn steal(&mut self, cpu: usize, runnable: &dyn Fn(&thread::Thread) -> bool) -> Option<Box<thread::Thread>> {
+        let count = smp::cpu_count();
+        let len = |p: &Option<Box<dyn SchedulingPolicy>>| p.as_ref().map_or(0, |p| p.len());
+        let busiest = (0..count).filter(|c| *c != cpu).max_by_key(|c| len(&self.policies[*c]))?;
+        let others = (1..count).map(|i| (cpu + i) % count).filter(|c| *c != busiest);
+        for victim in core::iter::once(busiest).chain(others) {
+            if len(&self.policies[victim]) == 0 {
+                continue;
+            }
+            let mut next = self.policy(victim).pick_next(runnable);
+            if let Some(t) = next.as_mut() {
+                self.migrate(t.as_mut(), cpu);
+                return next;
+            }
+        }
+        None
+    }
+
+    /**
+        Description: Check if we can switch from the thread running on the calling cpu to another one. \
                      If doable prepare everything and return raw pointers to current and next thread. \
-                     The switching of threads is done from within the ISR of the PIT, in order to \
-                     release the lock of the scheduler. 
+                     The switching of threads is done from within the timer ISR, in order to \
+                     release the lock of the scheduler. The policy decides if the current thread \
+                     is preempted, the idle thread is left as soon as another thread is ready. \
+                     A ready real-time thread preempts normal threads and real-time threads \
+                     with a later deadline or without budget left.
 
         Return: \
                `(current,next)` current thread, next thread (to switch to)
@@ -72,32 +299,63 @@ impl Scheduler {
         if self.initialized == false {
             return (ptr::null_mut(), ptr::null_mut());
         }
-      /* Hier muss Code eingefuegt werden */
-      let cur = self.active;
-      unsafe {
-        self.ready_queue.enqueue(Box::from_raw(cur));
-      }
-      let next = self.ready_queue.dequeue();
-      if let Some(that) = next {
-        self.active = Box::into_raw(that);
-        return (cur, self.active);
-      } else {
-        return (ptr::null_mut(), ptr::null_mut());
-      }
-    } 
+        /* Hier muss Code eingefuegt werden */
+        let cpu = smp::cpu_id();
+        let cur = self.active[cpu];
+        if cur.is_null() {
+            return (ptr::null_mut(), ptr::null_mut());
+        }
+        if cur != self.idle[cpu] && self.must_preempt(cpu, cur) == false {
+            return (ptr::null_mut(), ptr::null_mut());
+        }
+        unsafe {
+            self.enqueue(Box::from_raw(cur));
+        }
+        let next = self.dequeue_next(cpu);
+        if let Some(that) = next {
+            self.active[cpu] = Box::into_raw(that);
+            return (cur, self.active[cpu]);
+        } else {
+            return (ptr::null_mut(), ptr::null_mut());
+        }
+    }
+
+    // Tick fuer den aktiven Thread 'cur' von 'cpu' verbuchen und entscheiden, ob er verdraengt wird
+    fn must_preempt(&mut self, cpu: usize, cur: *mut thread::Thread) -> bool {
+        if unsafe { (*cur).is_suspended() } {
+            return true;
+        }
+        let earliest = self.edf.earliest_deadline(&|t| t.may_run_on(cpu) && t.is_on_cpu() == false);
+        match unsafe { (*cur).get_realtime_mut() } {
+            Some(rt) => {
+                let runnable = rt.charge_tick();
+                runnable == false || earliest.map_or(false, |d| d < rt.get_abs_deadline())
+            }
+            None => {
+                let preempt = self.policy(cpu).on_tick(unsafe { &mut *cur });
+                preempt || earliest.is_some()
+            }
+        }
+    }
 
     /**
-     Description: Start the scheduler. Called only once from 'startup'
+     Description: Start the scheduler on the calling cpu. Called once from 'startup'
+                  and once by each application processor (see `smp`).
     */
     pub fn schedule() {
-        let next_thread = SCHEDULER.lock().ready_queue.dequeue();
+        let next_thread = SCHEDULER.lock().dequeue_next(smp::cpu_id());
         if let Some(that) = next_thread {
             // convert 'next_thread' into raw pointer.
             // Prevents Rust from deleting it too early but we need to manually call 'drop' later
             let raw = Box::into_raw(that);
 
-            // set active reference in SCHEDULER
-            SCHEDULER.lock().active = raw;
+            // set active reference in SCHEDULER and allow preemption
+            // (not only when the idle thread runs first, it might have the lowest priority)
+            {
+                let mut s = SCHEDULER.lock();
+                s.set_active(raw);
+                s.initialized = true;
+            }
 
             // and start this thread
             thread::Thread::start(raw);
@@ -111,61 +369,597 @@ impl Scheduler {
 
         Parameters: \
                `that` thread to be registered
+
+        Return: \
+               handle for waiting on the end of the thread
     */
-    pub fn ready(that: Box<thread::Thread>) {
-        SCHEDULER.lock().ready_queue.enqueue(that);
+    pub fn ready(mut that: Box<thread::Thread>) -> thread::JoinHandle {
+        assert!(that.get_realtime().is_none(), "Scheduler::ready: use 'ready_realtime'");
+        let handle = that.join_handle();
+        let mut s = SCHEDULER.lock();
+        s.threads.push(that.get_raw_pointer());
+        s.wakeup(that);
+        handle
     }
 
     /**
-        Description: Calling thread terminates. Scheduler switches to next thread.
-                     (The thread terminating is not in the ready queue.)
+        Description: Register the idle thread of `cpu`. It is not inserted into a ready queue
+                     but runs only if no other thread is ready on `cpu`.
+
+        Parameters: \
+               `cpu` number of the cpu, see `smp::cpu_id` \
+               `that` the idle thread, should be bound to `cpu` (see `Builder::affinity`)
     */
-    pub fn exit() {
-        // Get next thread from ready queue
-        let next = SCHEDULER.lock().ready_queue.dequeue();
-        if next.is_none() {
-            panic!("Cannot exit thread as there is no other thread to run!");
+    pub fn set_idle_thread(cpu: usize, mut that: Box<thread::Thread>) {
+        let mut s = SCHEDULER.lock();
+        s.threads.push(that.get_raw_pointer());
+        s.idle[cpu] = Box::into_raw(that);
+    }
+
+    /**
+        Description: Register new real-time thread, if it passes the admission control
+                     (see `Edf::admit`). Its first period starts now.
+
+        Parameters: \
+               `that` thread to be registered, created with `Builder::realtime`
+
+        Return: \
+               handle for waiting on the end of the thread, `None` if rejected
+    */
+    pub fn ready_realtime(mut that: Box<thread::Thread>) -> Option<thread::JoinHandle> {
+        let handle = that.join_handle();
+        let was_enabled = cpu::disable_int_nested();
+        let admitted = {
+            let mut s = SCHEDULER.lock();
+            let rt = that.get_realtime_mut().expect("Scheduler::ready_realtime: no real-time thread");
+            if s.edf.admit(rt, pit::get_systime()) {
+                s.threads.push(that.get_raw_pointer());
+                s.enqueue(that);
+                true
+            } else {
+                kprintln!("ready_realtime: thread {} rejected by admission control", that);
+                drop(s);
+                drop(that);
+                false
+            }
+        };
+        cpu::enable_int_nested(was_enabled);
+        if admitted {
+            Some(handle)
+        } else {
+            None
         }
+    }
 
-        // Start next thread
-        if let Some(nx) = next {
-            let raw = Box::into_raw(nx);
-            SCHEDULER.lock().active = raw;
-            thread::Thread::start(raw);
+    /**
+        Description: Called by a real-time thread when the work of the current period
+                     is done. Blocks until the next period begins.
+    */
+    pub fn wait_next_period() {
+        let was_enabled = cpu::disable_int_nested();
+        let (cur, next) = {
+            let mut s = SCHEDULER.lock();
+            let cpu = smp::cpu_id();
+            let cur = s.active[cpu];
+            if thread::Thread::is_realtime(cur) == false {
+                panic!("wait_next_period: thread {} is no real-time thread", unsafe { &*cur });
+            }
+            s.edf.complete(unsafe { Box::from_raw(cur) }, pit::get_systime());
+            let next = Box::into_raw(s.dequeue_next(cpu).unwrap());
+            s.active[cpu] = next;
+            (cur, next)
+        };
+        thread::Thread::switch(cur, next);
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    /**
+        Description: Start new periods of real-time threads and count missed deadlines.
+                     Called by the timer ISRs with the scheduler locked.
+    */
+    pub fn release_realtime(&mut self) {
+        let now = pit::get_systime();
+        for t in self.threads.iter().filter(|t| unsafe { (***t).is_suspended() } == false) {
+            if let Some(rt) = unsafe { (**t).get_realtime_mut() } {
+                rt.check_deadline(now);
+            }
+        }
+        self.edf.release_jobs(now);
+    }
+
+    /**
+        Description: Select the scheduling policy, each cpu gets its own policy object.
+                     Called at boot before threads are created, threads already ready
+                     are moved to the new policies.
+
+        Parameters: \
+               `name` name of the policy, see `policy::from_name`
+
+        Return: \
+               `false` if there is no policy `name`
+    */
+    pub fn set_policy(name: &str) -> bool {
+        if policy::from_name(name).is_none() {
+            return false;
         }
+        let was_enabled = cpu::disable_int_nested();
+        {
+            let mut s = SCHEDULER.lock();
+            for cpu in 0..consts::MAX_CPUS {
+                let old = s.policies[cpu].replace(policy::from_name(name).unwrap());
+                if let Some(mut old) = old {
+                    while let Some(t) = old.pick_next(&|_| true) {
+                        s.enqueue(t);
+                    }
+                }
+            }
+        }
+        cpu::enable_int_nested(was_enabled);
+        true
+    }
+
+    /**
+        Description: Name of the scheduling policy
+    */
+    pub fn get_policy_name() -> &'static str {
+        SCHEDULER.lock().policy(0).name()
+    }
+
+    /**
+        Description: Check if the idle thread is running on the calling cpu.
+                     Used by the PIT ISR for the cpu load.
+    */
+    pub fn is_idle_active(&self) -> bool {
+        let cpu = smp::cpu_id();
+        self.active[cpu].is_null() == false && self.active[cpu] == self.idle[cpu]
+    }
+
+    /**
+        Description: Calling thread terminates with exit code `0`. See `exit_with_code`.
+    */
+    pub fn exit() -> ! {
+        Scheduler::exit_with_code(0)
     }
 
     /**
-        Description: Yield cpu and switch to next thread
+        Description: Calling thread terminates. Scheduler switches to next thread.
+                     (The thread terminating is not in the ready queue.) \
+                     Threads waiting in `JoinHandle::join` are deblocked and get `code`.
+                     The thread is handed to the reaper which frees its memory later,
+                     as we are still running on its stack.
+
+        Parameters: \
+               `code` exit code returned by `JoinHandle::join`
+    */
+    pub fn exit_with_code(code: i32) -> ! {
+        cpu::disable_int();
+        let active = SCHEDULER.lock().active();
+        thread::Thread::finish(active, code);
+
+        let (cur, next) = {
+            let mut s = SCHEDULER.lock();
+            let cpu = smp::cpu_id();
+            let cur = s.active[cpu];
+            s.block_active();
+            let next = s.dequeue_next(cpu);
+            if next.is_none() {
+                panic!("Cannot exit thread as there is no other thread to run!");
+            }
+            s.active[cpu] = Box::into_raw(next.unwrap());
+            s.bury(unsafe { Box::from_raw(cur) });
+            (cur, s.active[cpu])
+        };
+
+        // Start next thread, 'cur' is still valid as the reaper does not free it
+        // before 'Thread::finish_switch' (see 'reap')
+        thread::Thread::switch(cur, next);
+        panic!("exit: dead thread has been resumed");
+    }
+
+    /**
+        Description: Yield cpu and switch to next thread. \
+                     The calling thread keeps the cpu if the policy picks it again.
     */
     pub fn yield_cpu() {
         /* Hier muss Code eingefuegt werden */
-        let next_thread = SCHEDULER.lock().ready_queue.dequeue();
+        let was_enabled = cpu::disable_int_nested();
+        let (cur, next) = {
+            let mut s = SCHEDULER.lock();
+            let cpu = smp::cpu_id();
+            let cur = s.active[cpu];
+            s.enqueue(unsafe { Box::from_raw(cur) });
+            let next = Box::into_raw(s.dequeue_next(cpu).unwrap());
+            s.active[cpu] = next;
+            (cur, next)
+        };
+        if cur != next {
+            thread::Thread::switch(cur, next);
+        }
+        cpu::enable_int_nested(was_enabled);
+    }
 
-        if let Some(mut that) = next_thread {
-            let current_active = SCHEDULER.lock().active;
-            SCHEDULER.lock().ready_queue.enqueue(unsafe { Box::from_raw(current_active) });
-            SCHEDULER.lock().active = that.as_mut();
-            thread::Thread::switch(current_active, Box::into_raw(that));
-        } else {
+    /**
+        Description: Block the calling thread for (at least) `ticks` PIT ticks of 10ms.
+                     The thread is parked in the sleep queue and is put back into
+                     the ready queue by `wakeup_sleepers`, called from the PIT ISR.
+                     Before the scheduler runs we can only busy wait.
+
+        Parameters: \
+               `ticks` number of ticks to sleep
+    */
+    pub fn sleep(ticks: u64) {
+        if ticks == 0 {
+            Scheduler::yield_cpu();
             return;
         }
+
+        let was_enabled = cpu::disable_int_nested();
+        let (cur, next) = SCHEDULER.lock().prepare_sleep(pit::get_systime() + ticks);
+
+        if cur.is_null() || next.is_null() {
+            cpu::enable_int_nested(was_enabled);
+            let start = pit::get_systime();
+            while pit::get_systime() < start + ticks {}
+            return;
+        }
+
+        thread::Thread::switch(cur, next);
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    /**
+        Description: Like `prepare_block`, but the current thread is parked in the sleep
+                     queue until the systime `wakeup`.
+
+        Return: \
+               `(current,next)` current thread, next thread (to switch to), both null
+               if the scheduler is not running yet
+    */
+    pub fn prepare_sleep(&mut self, wakeup: u64) -> (*mut thread::Thread, *mut thread::Thread) {
+        let (cur, next) = self.prepare_block();
+        if cur.is_null() == false && next.is_null() == false {
+            let mut that = unsafe { Box::from_raw(cur) };
+            that.set_wakeup_time(wakeup);
+            that.set_state(thread::ThreadState::Sleeping);
+
+            // Absteigend sortiert, damit die faelligen Threads am Ende liegen.
+            // Bei gleicher Weckzeit werden die Threads in FIFO-Reihenfolge geweckt.
+            let pos = self
+                .sleep_queue
+                .iter()
+                .position(|t| t.get_wakeup_time() <= wakeup)
+                .unwrap_or(self.sleep_queue.len());
+            self.sleep_queue.insert(pos, that);
+        }
+        (cur, next)
+    }
+
+    /**
+        Description: Same as `sleep` but the time is given in ms (rounded up to full ticks).
+    */
+    pub fn sleep_ms(ms: u64) {
+        Scheduler::sleep((ms + pit::TICK_MS - 1) / pit::TICK_MS);
+    }
+
+    /**
+        Description: Account the current tick to the thread running on the calling cpu.
+                     Called by the timer ISR with the scheduler locked.
+    */
+    pub fn account_tick(&mut self) {
+        let active = self.active();
+        if active.is_null() == false {
+            unsafe { (*active).add_tick() };
+        }
+    }
+
+    /**
+        Description: Make the threads ready which have been deblocked by an ISR while
+                     the scheduler was locked, see `deblock_from_isr`. Called by the
+                     timer ISRs with the scheduler locked.
+    */
+    pub fn wakeup_deferred(&mut self) {
+        let mut that = DEFERRED.swap(ptr::null_mut(), Ordering::SeqCst);
+        while that.is_null() == false {
+            let next = thread::Thread::get_deferred_next(that);
+            thread::Thread::set_deferred_next(that, ptr::null_mut());
+            thread::Thread::set_blocked_in(that, ptr::null());
+            self.wakeup(unsafe { Box::from_raw(that) });
+            that = next;
+        }
+    }
+
+    /**
+        Description: Move all sleeping threads whose wake-up time has been reached
+                     into the ready queue, also blocked threads whose timeout expired
+                     (see `prepare_block_until`). Called by the timer ISRs with the
+                     scheduler locked.
+    */
+    pub fn wakeup_sleepers(&mut self) {
+        let now = pit::get_systime();
+        self.expire_timeouts(now);
+        loop {
+            let due = match self.sleep_queue.last() {
+                Some(t) => t.get_wakeup_time() <= now,
+                None => false,
+            };
+            if due == false {
+                break;
+            }
+            let that = self.sleep_queue.pop().unwrap();
+            self.wakeup(that);
+        }
+    }
+
+    /**
+        Description: Change the priority of thread `tid`. A ready thread is
+                     taken from the policy and enqueued again with its new priority.
+
+        Parameters: \
+               `tid` id of the thread, `prio` new priority
+
+        Return: \
+               `false` if there is no thread `tid`
+    */
+    pub fn set_priority(tid: usize, prio: usize) -> bool {
+        let was_enabled = cpu::disable_int_nested();
+        let found = SCHEDULER.lock().change_priority(tid, prio);
+        cpu::enable_int_nested(was_enabled);
+        found
+    }
+
+    /**
+        Description: Restrict thread `tid` to the cpus in `mask` (bit n = cpu n). A ready
+                     thread is moved to an allowed cpu at once, a running thread when it
+                     is preempted next.
+
+        Parameters: \
+               `tid` id of the thread, `mask` allowed cpus, must contain a running cpu
+
+        Return: \
+               `false` if there is no thread `tid` or `mask` contains no running cpu
+    */
+    pub fn set_affinity(tid: usize, mask: u64) -> bool {
+        if mask & smp::cpu_mask() == 0 {
+            return false;
+        }
+        let was_enabled = cpu::disable_int_nested();
+        let found = {
+            let mut s = SCHEDULER.lock();
+            if let Some(mut t) = s.remove_ready(tid) {
+                t.set_affinity(mask);
+                s.enqueue(t);
+                true
+            } else {
+                match s.find(tid) {
+                    Some(t) => {
+                        unsafe { (*t).set_affinity(mask) };
+                        true
+                    }
+                    None => false,
+                }
+            }
+        };
+        cpu::enable_int_nested(was_enabled);
+        found
+    }
+
+    fn change_priority(&mut self, tid: usize, prio: usize) -> bool {
+        if let Some(mut t) = self.remove_ready(tid) {
+            t.set_priority(prio);
+            self.enqueue(t);
+            return true;
+        }
+        // aktiver oder blockierter Thread, wirkt beim naechsten Eintragen in die Ready-Queue
+        match self.find(tid) {
+            Some(t) => {
+                unsafe { (*t).set_priority(prio) };
+                true
+            }
+            None => false,
+        }
     }
 
     /**
-        Description: Kill thread with given thread id. The thread will just be
-                     removed from the ready queue.
+        Description: Kill thread with given thread id. The thread is removed
+                     from the ready queue, the sleep queue, the suspended threads or from the wait queue
+                     of the mutex it is blocked in and handed to the reaper. Threads waiting
+                     in `JoinHandle::join` get `EXIT_CODE_KILLED`. \
+                     A running thread cannot be killed, neither the calling thread
+                     nor a thread running on another cpu. Such a thread must end
+                     itself, e.g. after checking a flag.
 
         Parameters: \
                `tokill_tid` id of the thread to be killed. Calling thread cannot kill itself.
+
+        Return: \
+               `true` if the thread has been killed, `false` if it is unknown or running
     */
-    pub fn kill(tokill_tid: usize) {
+    pub fn kill(tokill_tid: usize) -> bool {
 
         /* Hier muss Code eingefuegt werden */
         if tokill_tid == get_active_tid(){
-            return;
+            return false;
+        }
+
+        let was_enabled = cpu::disable_int_nested();
+        let victim = loop {
+            let mut s = SCHEDULER.lock();
+
+            // Thread in einer Ready-Queue, in der Sleep-Queue oder suspendiert?
+            // Von ISRs deblockierte Threads werden vorher bereit gemacht.
+            s.wakeup_deferred();
+            let found = s
+                .remove_ready(tokill_tid)
+                .or_else(|| s.remove_sleeping(tokill_tid))
+                .or_else(|| s.remove_suspended(tokill_tid));
+            if found.is_some() {
+                break found;
+            }
+
+            // Sonst in einer Warteschlange blockiert? 'blocked_in' wird nur mit
+            // gesperrtem Scheduler geloescht ('deblock'), solange wir ihn sperren,
+            // blockiert der Thread noch und die Warteschlange existiert.
+            let wait_queue = match s.find(tokill_tid) {
+                Some(t) => thread::Thread::get_blocked_in(t),
+                None => ptr::null(),
+            };
+            if wait_queue.is_null() {
+                // unbekannt oder laeuft (evt. auf einer anderen CPU)
+                break None;
+            }
+
+            // Die Warteschlange wird sonst vor dem Scheduler gesperrt (z.B. in
+            // 'Mutex::unlock'), daher nur versuchen. Fehlt der Thread, ist er
+            // schon ausgehaengt und wird gleich deblockiert.
+            if let Some(mut queue) = unsafe { (*wait_queue).try_lock() } {
+                let found = queue.remove_first(|t| thread::Thread::get_tid(t.as_ref()) == tokill_tid);
+                if found.is_some() {
+                    break found;
+                }
+            }
+            drop(s);
+            cpu::pause();
+        };
+
+        let killed = victim.is_some();
+        if let Some(mut t) = victim {
+            thread::Thread::finish(t.get_raw_pointer(), thread::EXIT_CODE_KILLED);
+            SCHEDULER.lock().bury(t);
+        }
+        cpu::enable_int_nested(was_enabled);
+        killed
+    }
+
+    /**
+        Description: Suspend thread `tid` until `resume` is called. A ready thread
+                     is taken out of the ready queue at once, a running thread when
+                     it is preempted next (the calling thread gives up the cpu at
+                     once). A blocked or sleeping thread is suspended when it is
+                     deblocked or woken up.
+
+        Parameters: \
+               `tid` id of the thread, idle threads cannot be suspended
+
+        Return: \
+               `false` if there is no thread `tid`
+    */
+    pub fn suspend(tid: usize) -> bool {
+        let was_enabled = cpu::disable_int_nested();
+        let (found, is_active) = {
+            let mut s = SCHEDULER.lock();
+            match s.find(tid) {
+                Some(t) if s.idle.contains(&t) == false => {
+                    unsafe { (*t).set_suspended(true) };
+                    if let Some(t) = s.remove_ready(tid) {
+                        s.enqueue(t);
+                    }
+                    (true, t == s.active())
+                }
+                _ => (false, false),
+            }
+        };
+        if is_active {
+            Scheduler::yield_cpu();
+        }
+        cpu::enable_int_nested(was_enabled);
+        found
+    }
+
+    /**
+        Description: Continue the suspended thread `tid`. If its suspension has not
+                     taken effect yet (see `suspend`), it is just cancelled.
+                     A real-time thread starts a new period.
+
+        Return: \
+               `false` if there is no thread `tid` or it is not suspended
+    */
+    pub fn resume(tid: usize) -> bool {
+        let was_enabled = cpu::disable_int_nested();
+        let found = {
+            let mut s = SCHEDULER.lock();
+            match s.remove_suspended(tid) {
+                Some(mut t) => {
+                    t.set_suspended(false);
+                    if let Some(rt) = t.get_realtime_mut() {
+                        rt.restart(pit::get_systime());
+                    }
+                    s.wakeup(t);
+                    true
+                }
+                None => match s.find(tid) {
+                    Some(t) if unsafe { (*t).is_suspended() } => {
+                        unsafe { (*t).set_suspended(false) };
+                        true
+                    }
+                    _ => false,
+                },
+            }
+        };
+        cpu::enable_int_nested(was_enabled);
+        found
+    }
+
+    // Angehaltenen Thread 'tid' aushaengen
+    fn remove_suspended(&mut self, tid: usize) -> Option<Box<thread::Thread>> {
+        let pos = self
+            .suspended
+            .iter()
+            .position(|t| thread::Thread::get_tid(t.as_ref()) == tid)?;
+        Some(self.suspended.remove(pos))
+    }
+
+    // Schlafenden Thread 'tid' aus der Sleep-Queue aushaengen
+    fn remove_sleeping(&mut self, tid: usize) -> Option<Box<thread::Thread>> {
+        let pos = self
+            .sleep_queue
+            .iter()
+            .position(|t| thread::Thread::get_tid(t.as_ref()) == tid)?;
+        Some(self.sleep_queue.remove(pos))
+    }
+
+    // Lebenden Thread 'tid' suchen (egal ob aktiv, bereit oder blockiert)
+    fn find(&self, tid: usize) -> Option<*mut thread::Thread> {
+        self.threads.iter().copied().find(|t| thread::Thread::get_tid(*t) == tid)
+    }
+
+    // Beendeten Thread an den Reaper uebergeben und diesen ggf. aufwecken
+    fn bury(&mut self, mut that: Box<thread::Thread>) {
+        let raw = that.get_raw_pointer();
+        self.threads.retain(|t| *t != raw);
+        self.cancel_timeout(raw);
+        if let Some(rt) = that.get_realtime() {
+            self.edf.release_share(rt);
+        }
+        that.set_state(thread::ThreadState::Dead);
+        self.dead.push(that);
+
+        if self.reaper.is_null() == false {
+            let reaper = self.reaper;
+            self.reaper = ptr::null_mut();
+            self.wakeup(unsafe { Box::from_raw(reaper) });
         }
-        //SCHEDULER.lock().ready_queue.remove(thread::Thread::new(tokill_tid,thread::kickoff_kernel_thread, true));
+    }
+
+    /**
+        Description: Called by the reaper thread. Blocks the caller until a
+                     thread has been buried, unless `dead` is not empty.
+    */
+    pub fn wait_for_dead() {
+        let was_enabled = cpu::disable_int_nested();
+        let (cur, next) = {
+            let mut s = SCHEDULER.lock();
+            if s.dead.is_empty() == false {
+                (ptr::null_mut(), ptr::null_mut())
+            } else {
+                let (cur, next) = s.prepare_block();
+                s.reaper = cur;
+                (cur, next)
+            }
+        };
+        if cur.is_null() == false && next.is_null() == false {
+            thread::Thread::switch(cur, next);
+        }
+        cpu::enable_int_nested(was_enabled);
     }
 
     
@@ -174,11 +968,12 @@ impl Scheduler {
                      If doable prepare everything and return raw pointers to current and next thread. \
                      The switching of threads is done later by calling 'Thread::switch'. \
                      This function is very similar to `prepare_preempt` except the \
-                     current thread is not inserted in the `ready_queue` but returned. \
-                     The next thread is removed from the `ready_queue` and `active` is set.
+                     current thread is not handed to the policy but returned. \
+                     The next thread is taken from the policy and `active` is set.
 
         Return: \
-               `(current,next)` current thread, next thread (to switch to)
+               `(current,next)` current thread, next thread (to switch to) \
+               both null if the scheduler is not initialized or no thread is active
     */
     pub fn prepare_block(&mut self) -> (*mut thread::Thread, *mut thread::Thread) {
         // If the scheduler is not initialized, we abort
@@ -186,13 +981,78 @@ impl Scheduler {
             return (ptr::null_mut(), ptr::null_mut());
         }
         /* Hier muss Code eingefuegt werden */
-        let cur = self.active;
-        let next = self.ready_queue.dequeue();
+        let cpu = smp::cpu_id();
+        let cur = self.active[cpu];
+        if cur.is_null() {
+            return (ptr::null_mut(), ptr::null_mut());
+        }
+        unsafe { (*cur).set_state(thread::ThreadState::Blocked) };
+        self.block_active();
+        let next = self.dequeue_next(cpu);
         if let Some(that) = next {
-            self.active = Box::into_raw(that);
-        return (cur, self.active);
+            self.active[cpu] = Box::into_raw(that);
+            return (cur, self.active[cpu]);
         } else {
-        panic!("No thread to switch to");
+            panic!("No thread to switch to");
+        }
+    }
+
+    /**
+        Description: Like `prepare_block`, but the timer ISR deblocks the thread at
+                     systime `wakeup`, if it is still in its wait queue then. The caller
+                     must enqueue the thread and set its wait queue with
+                     `Thread::set_blocked_in` before the queue is unlocked.
+                     `Thread::take_timed_out` tells the woken thread which happened.
+
+        Return: \
+               `(current,next)` see `prepare_block`
+    */
+    pub fn prepare_block_until(&mut self, wakeup: u64) -> (*mut thread::Thread, *mut thread::Thread) {
+        let (cur, next) = self.prepare_block();
+        if !cur.is_null() {
+            let that = unsafe { &mut *cur };
+            that.set_wakeup_time(wakeup);
+            that.set_timed_out(false);
+            self.timeouts.push(cur);
+        }
+        (cur, next)
+    }
+
+    // Blockierte Threads mit abgelaufenem Timeout aus ihrer Warteschlange holen.
+    // Solange 'blocked_in' gesetzt ist, existiert die Warteschlange (siehe 'kill'),
+    // sie wird sonst vor dem Scheduler gesperrt, daher nur 'try_lock'.
+    fn expire_timeouts(&mut self, now: u64) {
+        let mut i = 0;
+        while i < self.timeouts.len() {
+            let t = self.timeouts[i];
+            let wait_queue = thread::Thread::get_blocked_in(t);
+            // noch nicht faellig oder noch nicht eingetragen
+            if unsafe { (*t).get_wakeup_time() } > now || wait_queue.is_null() {
+                i += 1;
+                continue;
+            }
+            let removed = match unsafe { (*wait_queue).try_lock() } {
+                Some(mut queue) => queue.remove_first(|w| ptr::eq(w.as_ref(), t)),
+                None => {
+                    // beim naechsten Tick wieder versuchen
+                    i += 1;
+                    continue;
+                }
+            };
+            self.timeouts.swap_remove(i);
+            // Fehlt der Thread, wurde er gerade benachrichtigt und 'deblock' folgt
+            if let Some(mut that) = removed {
+                that.set_timed_out(true);
+                thread::Thread::set_blocked_in(t, ptr::null());
+                self.wakeup(that);
+            }
+        }
+    }
+
+    // Timeout von 't' streichen, er wurde geweckt oder beendet
+    fn cancel_timeout(&mut self, t: *mut thread::Thread) {
+        if !self.timeouts.is_empty() {
+            self.timeouts.retain(|w| *w != t);
         }
     }
 }
@@ -205,14 +1065,107 @@ pub fn prepare_block() -> (*mut thread::Thread, *mut thread::Thread) {
     SCHEDULER.lock().prepare_block()
 }
 
+/**
+ Description: Prepare the blocking of the calling thread with a timeout,
+              see `Scheduler::prepare_block_until`
+*/
+pub fn prepare_block_until(wakeup: u64) -> (*mut thread::Thread, *mut thread::Thread) {
+    SCHEDULER.lock().prepare_block_until(wakeup)
+}
+
 /**
  Description: Deblock thread `that`. This will result in putting
               `that` into the ready-queue but no thread switching.
 */
 pub fn deblock(that: *mut thread::Thread) {
-    unsafe {
-        SCHEDULER.lock().ready_queue.enqueue(Box::from_raw(that));
+    // 'blocked_in' nur mit gesperrtem Scheduler loeschen, siehe 'Scheduler::kill'
+    let mut s = SCHEDULER.lock();
+    thread::Thread::set_blocked_in(that, ptr::null());
+    s.wakeup(unsafe { Box::from_raw(that) });
+}
+
+/**
+ Description: Same as `deblock` but may be called from an ISR. If the scheduler is
+              locked (maybe by the interrupted thread), `that` is made ready by the
+              next timer interrupt instead of spinning forever.
+*/
+pub fn deblock_from_isr(that: *mut thread::Thread) {
+    if let Some(mut s) = SCHEDULER.try_lock() {
+        thread::Thread::set_blocked_in(that, ptr::null());
+        s.wakeup(unsafe { Box::from_raw(that) });
+        return;
+    }
+    let mut head = DEFERRED.load(Ordering::SeqCst);
+    loop {
+        thread::Thread::set_deferred_next(that, head);
+        match DEFERRED.compare_exchange(head, that, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => break,
+            Err(h) => head = h,
+        }
+    }
+}
+
+/**
+ Description: Snapshot of all threads known to the scheduler, including
+              blocked and sleeping threads as well as dead threads which
+              have not been reaped yet. Sorted by thread id.
+*/
+pub fn thread_list() -> Vec<thread::ThreadInfo> {
+    let was_enabled = cpu::disable_int_nested();
+    let mut list: Vec<thread::ThreadInfo> = {
+        let s = SCHEDULER.lock();
+        s.threads
+            .iter()
+            .map(|t| unsafe { (**t).info() })
+            .chain(s.dead.iter().map(|t| t.info()))
+            .collect()
+    };
+    cpu::enable_int_nested(was_enabled);
+    list.sort_by_key(|info| info.tid);
+    list
+}
+
+/**
+ Description: Print all threads like `ps`
+*/
+pub fn ps() {
+    let list = thread_list();
+    println!("{}", thread::ThreadInfo::HEADER);
+    for info in list.iter() {
+        println!("{}", info);
+    }
+
+    // Echtzeit-Threads mit Periode, Budget, Deadline (in Ticks) und verpassten Deadlines
+    if list.iter().any(|info| info.realtime.is_some()) {
+        println!(" TID PERIOD BUDGET DEADLINE     JOBS   MISSES");
+        for info in list.iter() {
+            if let Some(rt) = info.realtime {
+                println!("{:>4} {}", info.tid, rt);
+            }
+        }
+    }
+}
+
+/**
+ Description: Free all threads which have been terminated by `exit` or `kill`.
+              Called by the reaper thread only. The memory is freed with
+              interrupts disabled, as the PIT must not preempt us while
+              we hold the allocator lock. Threads whose kernel stack is
+              still in use by a cpu (see `Thread::is_on_cpu`) are kept.
+*/
+pub fn reap() {
+    let was_enabled = cpu::disable_int_nested();
+    let dead: Vec<Box<thread::Thread>> = {
+        let mut s = SCHEDULER.lock();
+        let (dead, busy) = core::mem::take(&mut s.dead).into_iter().partition(|t| t.is_on_cpu() == false);
+        s.dead = busy;
+        dead
+    };
+    for t in dead.iter() {
+        kprintln!("reaper: freeing thread {}", t);
     }
+    drop(dead);
+    cpu::enable_int_nested(was_enabled);
 }
 
 
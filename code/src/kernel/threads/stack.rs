@@ -1,68 +1,321 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: stack                                                           ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Descr.: Allocating and deallocation memory for a stack.                 ║
+   ║ Descr.: Stacks of the threads. Each stack lies at the top of its own    ║
+   ║         virtual region of 'REGION_SIZE' bytes outside of the identity   ║
+   ║         mapping. Only the lowest page is backed at once, it holds a     ║
+   ║         canary which is checked on each thread switch. The other pages  ║
+   ║         get a frame on the first access, see 'handle_fault'. The rest   ║
+   ║         of the region below the stack (at least one page) is never      ║
+   ║         mapped and serves as guard area, an overflow causes a page      ║
+   ║         fault instead of overwriting memory.                            ║
+   ║                                                                         ║
+   ║         Kernel stacks are in 'SHARED_STACKS', whose page tables are the ║
+   ║         same in all address spaces. User stacks are in 'PRIVATE_STACKS' ║
+   ║         of the address space of their thread.                           ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Autor:  Michael Schoettner, 15.05.2023                                  ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
-use alloc::alloc::Layout;
 use alloc::boxed::Box;
-use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use crate::consts;
-use crate::kernel::allocator;
+use crate::kernel::address_space::AddressSpace;
 use crate::kernel::cpu;
+use crate::kernel::frames;
+use crate::kernel::paging::{self, PAGE_NO_EXECUTE, PAGE_PRESENT, PAGE_SIZE, PAGE_USER, PAGE_WRITABLE};
+use crate::kernel::smp;
 
+// Virtuelle Bereiche fuer die Stacks (PML4-Eintraege 1 und 2)
+pub const SHARED_STACKS: u64 = 0x0000_0080_0000_0000;
+pub const PRIVATE_STACKS: u64 = 0x0000_0100_0000_0000;
 
+// Groesse einer Region, ein Stack ist um die Guard-Page kleiner
+pub const REGION_SIZE: u64 = 0x100_0000;
+const MAX_REGIONS: usize = 4096;
+
+// Unterste Adresse des Stacks je Region (0 = frei), Bit 0 = User-Stack
+static REGIONS: [AtomicU64; MAX_REGIONS] = [const { AtomicU64::new(0) }; MAX_REGIONS];
+const REGION_USER: u64 = 1;
+
+// Thread-ID des Besitzers je Region, fuer die Meldung eines Ueberlaufs
+static OWNERS: [AtomicUsize; MAX_REGIONS] = [const { AtomicUsize::new(0) }; MAX_REGIONS];
+
+// Muster am unteren Ende des Stacks, wird bei jedem Threadwechsel geprueft.
+// Erkennt auch Ueberlaeufe, die den Guard-Bereich uebersprungen haben.
+const CANARY: u64 = 0xDEAD_C0DE_DEAD_C0DE;
+const CANARY_WORDS: usize = 8;
+
+// Wird beim Freigeben eines Stacks erhoeht, siehe 'sync_tlb'
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static SEEN_GENERATION: [AtomicU64; consts::MAX_CPUS] = [const { AtomicU64::new(0) }; consts::MAX_CPUS];
+
+// Oberes Ende der Region 'region' im Bereich 'base'
+fn region_top(base: u64, region: usize) -> u64 {
+    base + (region as u64 + 1) * REGION_SIZE
+}
+
+// Bits der Seitentabelleneintraege eines Stacks
+fn page_flags(user: bool) -> u64 {
+    let mut flags = PAGE_WRITABLE;
+    if paging::nx_enabled() {
+        flags |= PAGE_NO_EXECUTE;
+    }
+    if user {
+        flags |= PAGE_USER;
+    }
+    flags
+}
+
+/**
+ Description: Create the PML4 entry of `SHARED_STACKS` in the kernel address
+              space. Must be called before the first `AddressSpace::new`,
+              which copies the entry.
+*/
+pub fn init() {
+    if !paging::prepare_in(paging::kernel_pml4(), SHARED_STACKS, 0) {
+        panic!("stack::init: no frame for the page tables");
+    }
+}
+
+/**
+ Description: Result of `handle_fault`
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fault {
+    Resolved,                            // Seite wurde mit einem Frame hinterlegt
+    Overflow { tid: usize, user: bool }, // Zugriff unterhalb des Stacks (Guard-Bereich)
+    Unknown,                             // keine Adresse eines Stacks
+}
 
 #[repr(C)]
+#[derive(Default)]
 pub struct Stack {
-    data: *mut u8,
+    start: u64,    // unterste Adresse, 0 bei 'Stack::default'
     size: usize,
+    region: usize, // Index in 'REGIONS'
+    pml4: u64,     // Adressraum, in dem der Stack abgebildet ist
 }
 
 impl Stack {
-    pub fn new(size: usize) -> Box<Stack> {
-        // 64 bit alignment for stack
-        let layout = unsafe { Layout::from_size_align_unchecked(size, consts::STACK_ALIGNMENT) };
-
-        // alloc memory for stack and set ptr. to end of block - consts::STACK_ENTRY_SIZE
-        let start = allocator::alloc(layout);
-        let data = ((start as usize) + (size as usize) - consts::STACK_ENTRY_SIZE) as *mut u8;
-        if data.is_null() {
-            println!("Panic: failed in 'Stack::new'");
-            cpu::halt();
-        }
+    /**
+     Description: Reserve a kernel stack of `size` bytes (rounded up to pages)
+                  for the thread `tid`
+    */
+    pub fn new(size: usize, tid: usize) -> Box<Stack> {
+        Stack::reserve(size, tid, SHARED_STACKS, paging::kernel_pml4(), false)
+    }
+
+    /**
+     Description: Reserve a user stack of `size` bytes (rounded up to pages)
+                  for the thread `tid` in `space`. The stack must be dropped
+                  before `space`.
+    */
+    pub fn new_user(size: usize, tid: usize, space: &AddressSpace) -> Box<Stack> {
+        Stack::reserve(size, tid, PRIVATE_STACKS, space.get_pml4(), true)
+    }
 
-        kprintln!(
-            "Stack::new, memory block = [0x{:x}; 0x{:x}]",
-            start as usize,
-            (data as usize + consts::STACK_ENTRY_SIZE)
+    fn reserve(size: usize, tid: usize, base: u64, pml4: u64, user: bool) -> Box<Stack> {
+        let size = (size as u64 + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        assert!(
+            size <= REGION_SIZE - PAGE_SIZE,
+            "Stack::new: no room for the guard page, stack larger than 'REGION_SIZE'"
         );
 
-        Box::new(Stack { data, size })
+        // Freie Region suchen und belegen
+        let mut start = 0;
+        let region = (0..MAX_REGIONS).find(|r| {
+            start = region_top(base, *r) - size;
+            let value = start | if user { REGION_USER } else { 0 };
+            REGIONS[*r]
+                .compare_exchange(0, value, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        let region = match region {
+            Some(r) => r,
+            None => panic!("Stack::new: no free stack region"),
+        };
+        OWNERS[region].store(tid, Ordering::SeqCst);
+        let top = start + size;
+
+        // Seitentabellen anlegen, damit 'handle_fault' ohne Lock auskommt
+        let flags = page_flags(user);
+        let mut virt = start;
+        while virt < top {
+            if !paging::prepare_in(pml4, virt, flags) {
+                panic!("Stack::new: no frame for the page tables");
+            }
+            virt = (virt & !(paging::page_size(2) - 1)) + paging::page_size(2);
+        }
+
+        // Unterste Seite mit dem Canary sofort hinterlegen
+        let frame = frames::alloc_frame().expect("Stack::new: no free frame");
+        unsafe {
+            ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE as usize);
+            for i in 0..CANARY_WORDS {
+                *(frame as *mut u64).add(i) = CANARY;
+            }
+        }
+        if !paging::map_in(pml4, start, frame, flags) {
+            panic!("Stack::new: no frame for the page tables");
+        }
+
+        kprintln!("Stack::new, region {} = [0x{:x}; 0x{:x}]", region, start, top);
+
+        // Die Region kann vorher benutzt worden sein
+        sync_tlb();
+        Box::new(Stack {
+            start,
+            size: size as usize,
+            region,
+            pml4,
+        })
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /**
+     Description: Check if the canary at the low end of the stack is intact.
+                  Always `true` for an empty stack (`Stack::default`). Works
+                  in every address space.
+    */
+    pub fn canary_ok(&self) -> bool {
+        if self.start == 0 {
+            return true;
+        }
+        match paging::translate_in(self.pml4, self.start) {
+            Some(phys) => {
+                let words = phys as *const u64;
+                (0..CANARY_WORDS).all(|i| unsafe { *words.add(i) } == CANARY)
+            }
+            None => false,
+        }
+    }
+
+    /**
+     Description: Number of bytes backed with frames, the lowest page with
+                  the canary is not counted. Works in every address space.
+                  As pages are backed on the first access, this is the
+                  number of pages touched so far (not the bytes written, as
+                  before lazily backed stacks), in bytes.
+
+     Return: \
+        number of bytes used at most (high-water mark, whole pages)
+    */
+    pub fn high_water_mark(&self) -> usize {
+        if self.start == 0 {
+            return 0;
+        }
+        let pages = self.size as u64 / PAGE_SIZE;
+        (1..pages)
+            .filter(|p| paging::translate_in(self.pml4, self.start + p * PAGE_SIZE).is_some())
+            .count()
+            * PAGE_SIZE as usize
     }
 
+    // ptr. to end of block - consts::STACK_ENTRY_SIZE
     pub fn stack_end(&self) -> *mut u64 {
-        self.data as *mut u64
+        (self.start as usize + self.size - consts::STACK_ENTRY_SIZE) as *mut u64
     }
 }
 
 impl Drop for Stack {
     fn drop(&mut self) {
-        unsafe {
-            let layout = Layout::from_size_align_unchecked(self.size, consts::STACK_ALIGNMENT);
-            allocator::dealloc(self.data, layout);
+        if self.start == 0 {
+            return;
+        }
+        let top = self.start + self.size as u64;
+        let mut virt = self.start;
+        while virt < top {
+            if let Some(frame) = paging::unmap_in(self.pml4, virt) {
+                frames::free_frame(frame);
+            }
+            virt += PAGE_SIZE;
         }
+        // Andere CPUs koennen noch TLB-Eintraege haben, vor dem Freigeben der Region
+        GENERATION.fetch_add(1, Ordering::SeqCst);
+        REGIONS[self.region].store(0, Ordering::SeqCst);
     }
 }
 
-impl Default for Stack {
-    fn default() -> Self {
-        Self {
-            data: 0 as *mut u8,
-            size: 0,
+/**
+ Description: Back the page of a stack containing `addr` with a zeroed frame.
+              Called by the page fault handler for a not present page, so
+              only the active address space is used and no lock is taken,
+              see `paging::entry_in` and `frames::alloc_frame_for_fault`.
+
+ Parameters: \
+    `addr` faulting address (CR2) \
+    `user` the fault occurred in ring 3
+
+ Return: \
+    `Fault::Overflow` if `addr` is in the guard area below a stack, \
+    `Fault::Unknown` if `addr` is not inside a stack of the active address \
+    space, if ring 3 accessed a kernel stack or there is no free frame
+*/
+pub fn handle_fault(addr: u64, user: bool) -> Fault {
+    let base = if addr >= PRIVATE_STACKS {
+        PRIVATE_STACKS
+    } else if addr >= SHARED_STACKS {
+        SHARED_STACKS
+    } else {
+        return Fault::Unknown;
+    };
+    let region = ((addr - base) / REGION_SIZE) as usize;
+    if region >= MAX_REGIONS {
+        return Fault::Unknown;
+    }
+    let value = REGIONS[region].load(Ordering::SeqCst);
+    let start = value & !(PAGE_SIZE - 1);
+    let top = region_top(base, region);
+    if start == 0 || start < top - REGION_SIZE {
+        return Fault::Unknown;
+    }
+    // Kernel-Stacks (auch deren Guard-Bereich) sind im Ring 3 tabu
+    if user && value & REGION_USER == 0 {
+        return Fault::Unknown;
+    }
+    if addr < start {
+        return Fault::Overflow {
+            tid: OWNERS[region].load(Ordering::SeqCst),
+            user: value & REGION_USER != 0,
+        };
+    }
+
+    let entry = match paging::entry_in(paging::active_pml4(), addr) {
+        Some(e) => e,
+        None => return Fault::Unknown,
+    };
+    let page = addr & !(PAGE_SIZE - 1);
+    if entry.load(Ordering::SeqCst) & PAGE_PRESENT == 0 {
+        let frame = match frames::alloc_frame_for_fault() {
+            Some(f) => f,
+            None => return Fault::Unknown,
+        };
+        unsafe { ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE as usize) };
+        let pte = frame | PAGE_PRESENT | page_flags(value & REGION_USER != 0);
+        if entry.compare_exchange(0, pte, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            // Eine andere CPU war schneller
+            frames::free_frame_for_fault(frame);
         }
     }
-}
\ No newline at end of file
+    cpu::invlpg(page);
+    Fault::Resolved
+}
+
+/**
+ Description: Flush the TLB of the calling cpu if a stack has been freed
+              since the last call. Old entries could otherwise point to the
+              frames of a freed stack when its region is used again. Called
+              before a thread runs and when a stack is reserved.
+*/
+pub fn sync_tlb() {
+    let generation = GENERATION.load(Ordering::SeqCst);
+    if SEEN_GENERATION[smp::cpu_id()].swap(generation, Ordering::SeqCst) != generation {
+        paging::flush_all();
+    }
+}
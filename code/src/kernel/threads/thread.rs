@@ -6,15 +6,34 @@
    ║ Autor:  Michael Schoettner, 11.06.2024                                  ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
+use alloc::alloc::{dealloc, Layout};
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
 use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 
 use crate::consts;
 use crate::devices::cga;
+use crate::devices::pit;
+use crate::kernel::address_space::AddressSpace;
 use crate::kernel::cpu;
+use crate::kernel::interrupts::intdispatcher::INT_VEC_SYSCALL;
+use crate::kernel::lockdep::{self, HeldLocks};
+use crate::kernel::paging;
+use crate::kernel::smp;
+use crate::kernel::syscall::SYSNO_EXIT;
+use crate::kernel::threads::realtime::RealTime;
 use crate::kernel::threads::scheduler;
 use crate::kernel::threads::stack;
-use crate::mylib::queue::Link;
+use crate::mylib::mutex::HeldLock;
+use crate::mylib::queue::{Link, Queue};
+use crate::mylib::spinlock::Spinlock;
 
 // Diese Funktionen sind in 'thread.asm'
 extern "C" {
@@ -28,10 +47,154 @@ extern "C" {
     fn _tss_set_rsp0(old_rsp0: u64);
 }
 
+// Je CPU der Thread, der zuletzt per 'switch' verlassen wurde. Er laeuft bis
+// 'finish_switch' noch auf seinem Stack, siehe 'Thread::on_cpu'.
+static PREV: [AtomicPtr<Thread>; consts::MAX_CPUS] = [const { AtomicPtr::new(ptr::null_mut()) }; consts::MAX_CPUS];
+
+// Je CPU der laufende Thread, fuer Meldungen aus Exceptions (ohne Scheduler-Lock)
+static CURRENT: [AtomicPtr<Thread>; consts::MAX_CPUS] = [const { AtomicPtr::new(ptr::null_mut()) }; consts::MAX_CPUS];
+
+// Zustand eines Threads, wird vom Scheduler gesetzt
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ThreadState {
+    Ready,
+    Running,
+    Blocked,
+    Sleeping,
+    Suspended,
+    Dead,
+}
+
+impl fmt::Display for ThreadState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ThreadState::Ready => "ready",
+            ThreadState::Running => "running",
+            ThreadState::Blocked => "blocked",
+            ThreadState::Sleeping => "sleeping",
+            ThreadState::Suspended => "suspend",
+            ThreadState::Dead => "dead",
+        };
+        f.pad(s)
+    }
+}
+
+// Exit-Code eines Threads, der durch 'Scheduler::kill' beendet wurde
+pub const EXIT_CODE_KILLED: i32 = -1;
+// Exit-Code eines User-Threads, der wegen einer Exception im Ring 3 beendet wurde
+pub const EXIT_CODE_FAULT: i32 = -2;
+
+// Exit-Code und auf das Ende wartende Threads. Wird von einem Thread und
+// seinen JoinHandles gemeinsam genutzt und ueberlebt daher den Thread.
+// 'code' wird nur mit gesperrtem 'joiners' gesetzt, siehe 'JoinHandle::join'.
+struct ExitState {
+    code: Spinlock<Option<i32>>,                 // None, solange der Thread laeuft
+    joiners: Spinlock<Queue<Box<Thread>>>,       // in 'join' blockierte Threads
+}
+
+// 'Thread' enthaelt Roh-Zeiger und ist daher nicht 'Send', die Threads in
+// 'joiners' werden aber nur mit gesperrter Warteschlange angefasst
+unsafe impl Send for ExitState {}
+unsafe impl Sync for ExitState {}
+
+// Einstiegsfunktion eines Threads
+enum Entry {
+    Closure(Box<dyn FnOnce() + Send>), // Kernel-Thread, wird in 'kickoff_kernel_thread' aufgerufen
+    User(UserEntry),                   // User-Thread, laeuft im Ring 3, siehe 'UserEntry'
+}
+
+impl Entry {
+    fn new<F>(f: F, kernel_thread: bool) -> Entry
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if kernel_thread {
+            Entry::Closure(Box::new(f))
+        } else {
+            Entry::User(UserEntry::new(f))
+        }
+    }
+}
+
+//
+// Closure eines User-Threads. Im Ring 3 ist der Heap nicht zugreifbar, daher
+// wird die Closure vor dem Start auf den User-Stack kopiert und dort von
+// 'call_closure' aufgerufen. Danach wird der Thread ueber 'user_exit' beendet.
+// Die Kopie ist bitweise, daher darf die Closure nichts besitzen, das einen
+// Destruktor hat (Box, Vec, Arc, ...), wie bei 'F: Copy'. Sonst wuerde der
+// Speicher im Ring 3 benutzt bzw. freigegeben.
+//
+struct UserEntry {
+    closure: *mut u8, // auf dem Heap, null nach dem Kopieren
+    layout: Layout,
+    call: extern "C" fn(*mut u8), // 'call_closure::<F>'
+}
+
+impl UserEntry {
+    fn new<F>(f: F) -> UserEntry
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // 'Builder::build' ist auch fuer Kernel-Threads generisch, daher hier
+        // zur Laufzeit statt mit 'F: Copy' (siehe 'spawn_user')
+        assert!(
+            !core::mem::needs_drop::<F>(),
+            "thread: the closure of a user thread must not own data with a destructor"
+        );
+        UserEntry {
+            closure: Box::into_raw(Box::new(f)) as *mut u8,
+            layout: Layout::new::<F>(),
+            call: call_closure::<F>,
+        }
+    }
+
+    // Closure nach 'dst' verschieben und den Speicher auf dem Heap freigeben
+    fn move_to(mut self, dst: *mut u8) {
+        unsafe {
+            ptr::copy_nonoverlapping(self.closure, dst, self.layout.size());
+            if self.layout.size() != 0 {
+                dealloc(self.closure, self.layout);
+            }
+        }
+        self.closure = ptr::null_mut();
+    }
+}
+
+// Der Thread wurde nie gestartet, die Closure hat keinen Destruktor (siehe 'new')
+impl Drop for UserEntry {
+    fn drop(&mut self) {
+        if !self.closure.is_null() && self.layout.size() != 0 {
+            unsafe { dealloc(self.closure, self.layout) };
+        }
+    }
+}
+
+// Laeuft im Ring 3 auf dem User-Stack, 'closure' zeigt auf die Kopie dort
+extern "C" fn call_closure<F: FnOnce()>(closure: *mut u8) {
+    let f = unsafe { ptr::read(closure as *mut F) };
+    f();
+}
+
+// Ruecksprungadresse von 'call_closure' (Ring 3), beendet den Thread mit dem
+// Systemaufruf 'SYSNO_EXIT' (Code 0). Der Stack ist hier nicht ausgerichtet,
+// daher direkt ohne Aufruf von 'usrlib::usr_exit'.
+extern "C" fn user_exit() -> ! {
+    unsafe {
+        asm!(
+            "xor edi, edi",
+            "int {vec}",
+            vec = const INT_VEC_SYSCALL,
+            in("rax") SYSNO_EXIT,
+            options(noreturn)
+        )
+    }
+}
+
 // Verwaltungsstruktur fuer einen Thread
 #[repr(C)]
 pub struct Thread {
     tid: usize,
+    name: String,
     is_kernel_thread: bool,
     old_rsp0: u64, // letzter genutzter Stackeintrag im Kernel-Stack
     // der User-Stack-Ptr. wird auto. durch die Hardware gesichert
@@ -42,18 +205,68 @@ pub struct Thread {
         Hier muss Code eingefuegt werden
     */
 
-    user_stack: Box<stack::Stack>,
+    user_stack: ManuallyDrop<Box<stack::Stack>>, // wird in 'drop' vor 'address_space' freigegeben
     kernel_stack: Box<stack::Stack>, // Speicher fuer den Kernel-Stack
-    entry: extern "C" fn(),
+    entry: Option<Entry>, // wird in 'kickoff_*_thread' einmal aufgerufen
+    address_space: Option<Arc<AddressSpace>>, // Adressraum eines User-Threads, None bei Kernel-Threads
+    priority: usize,  // Basis-Prioritaet (0 = niedrigste)
+    ready_since: u64, // Systemzeit des letzten Eintragens in eine Ready-Queue (Aging)
+    vruntime: u64,    // virtuelle Laufzeit (Fair-Share-Scheduling)
+    realtime: Option<RealTime>, // nur bei periodischen Echtzeit-Threads
+    affinity: u64,    // Bitmaske der CPUs, auf denen der Thread laufen darf
+    cpu: usize,       // CPU, auf der der Thread zuletzt lief
+    on_cpu: AtomicBool, // true, solange eine CPU auf dem Kernel-Stack des Threads laeuft
+    blocked_in: *const Spinlock<Queue<Box<Thread>>>, // Warteschlange, falls blockiert (sonst null, nur mit Scheduler-Lock geloescht)
+    wakeup_time: u64, // Systemzeit, zu der ein schlafender Thread (oder ein Timeout) geweckt wird
+    timed_out: bool,  // durch den Timeout geweckt, siehe 'Scheduler::prepare_block_until'
+    suspended: bool,  // soll angehalten werden bzw. ist angehalten, siehe 'Scheduler::suspend'
+    held_locks: Vec<HeldLock>, // gehaltene Mutexe und RwLocks, werden bei 'finish' freigegeben
+    deferred_next: *mut Thread, // Verkettung in 'scheduler::deblock_from_isr'
+    lockdep: HeldLocks, // gehaltene Locks, solange der Thread nicht laeuft (siehe 'lockdep')
+    state: ThreadState,
+    ticks: u64,         // Anzahl der Zeitscheiben (PIT-Ticks), in denen der Thread lief
+    switches: u64,      // wie oft der Thread die CPU bekommen hat
+    created: u64,       // Systemzeit beim Anlegen
+    exit_state: Arc<ExitState>,
 }
 
 impl Thread {
-    // Neuen Thread anlegen
-    pub fn new(my_tid: usize, myentry: extern "C" fn(), kernel_thread: bool) -> Box<Thread> {
+    // Neuen Thread anlegen (Name "thread-<tid>", Stacks mit 'consts::STACK_SIZE')
+    pub fn new(my_tid: usize, myentry: extern "C" fn(), kernel_thread: bool, my_priority: usize) -> Box<Thread> {
+        let builder = Builder::new().kernel_thread(kernel_thread).priority(my_priority);
+        Thread::create(my_tid, Entry::new(move || myentry(), kernel_thread), builder)
+    }
+
+    // Neuen Thread mit einer Closure als Einstiegsfunktion anlegen,
+    // die Thread-ID wird automatisch vergeben
+    pub fn new_closure<F>(f: F, kernel_thread: bool, my_priority: usize) -> Box<Thread>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        Builder::new().kernel_thread(kernel_thread).priority(my_priority).build(f)
+    }
+
+    fn create(my_tid: usize, myentry: Entry, builder: Builder) -> Box<Thread> {
+        assert!(builder.priority < consts::PRIORITY_LEVELS, "Thread::new: invalid priority");
+
+        // User-Threads bekommen einen eigenen Adressraum, falls keiner vorgegeben ist
+        let my_address_space = if builder.kernel_thread {
+            None
+        } else {
+            Some(builder.address_space.unwrap_or_else(AddressSpace::new))
+        };
 
-        // Speicher fuer die Stacks anlegen
-        let my_kernel_stack = stack::Stack::new(consts::STACK_SIZE);
-        let my_user_stack = stack::Stack::new(consts::STACK_SIZE);
+        // Stacks reservieren, Kernel-Threads brauchen keinen User-Stack
+        let my_kernel_stack = stack::Stack::new(builder.kernel_stack_size, my_tid);
+        let my_user_stack = match &my_address_space {
+            Some(space) => stack::Stack::new_user(builder.user_stack_size, my_tid, space),
+            None => Box::new(stack::Stack::default()),
+        };
+
+        let my_name = match builder.name {
+            Some(n) => n,
+            None => format!("thread-{}", my_tid),
+        };
 
         /*
            Hier muss Code eingefuegt werden
@@ -62,11 +275,35 @@ impl Thread {
         // Thread-Objekt anlegen
         let mut threadobj = Box::new(Thread {
             tid: my_tid,
-            is_kernel_thread: kernel_thread,
+            name: my_name,
+            is_kernel_thread: builder.kernel_thread,
             old_rsp0: 0,
-            user_stack: my_user_stack,
+            user_stack: ManuallyDrop::new(my_user_stack),
             kernel_stack: my_kernel_stack,
-            entry: myentry,
+            entry: Some(myentry),
+            address_space: my_address_space,
+            priority: builder.priority,
+            ready_since: 0,
+            vruntime: 0,
+            realtime: builder.realtime,
+            affinity: builder.affinity,
+            cpu: 0,
+            on_cpu: AtomicBool::new(false),
+            blocked_in: ptr::null(),
+            wakeup_time: 0,
+            timed_out: false,
+            suspended: false,
+            held_locks: Vec::new(),
+            deferred_next: ptr::null_mut(),
+            lockdep: HeldLocks::new(),
+            state: ThreadState::Ready,
+            ticks: 0,
+            switches: 0,
+            created: pit::get_systime(),
+            exit_state: Arc::new(ExitState {
+                code: Spinlock::new(None),
+                joiners: Spinlock::new(Queue::new()),
+            }),
         });
 
         threadobj.prepare_kernel_stack();
@@ -79,7 +316,11 @@ impl Thread {
     // Alle anderen Threads werden mit 'switch' angestossen
     pub fn start(now: *mut Thread) {
         unsafe {
-            kprintln!("thread start, kernel-stack = {:x}", (*now).old_rsp0);
+            (*now).switches += 1;
+            kprintln!("thread start: {}, kernel-stack = {:x}", *now, (*now).old_rsp0);
+            Thread::load_address_space(now);
+            stack::sync_tlb();
+            CURRENT[smp::cpu_id()].store(now, Ordering::SeqCst);
             _thread_kernel_start((*now).old_rsp0);
         }
     }
@@ -88,20 +329,69 @@ impl Thread {
     pub fn switch(now: *mut Thread, then: *mut Thread) {
         unsafe {
             kprint!(
-                "preempt: tid={}, old_rsp0={:x}",
-                Thread::get_tid(now),
+                "preempt: {}, old_rsp0={:x}",
+                *now,
                (*now).old_rsp0);
             kprintln!(
-                " and switch to tid={}, old_rsp0={:x}",
-                Thread::get_tid(then),
+                " and switch to {}, old_rsp0={:x}",
+                *then,
                 (*then).old_rsp0
             );
+            Thread::check_stacks(now);
+            Thread::check_stacks(then);
+            (*then).switches += 1;
+            lockdep::switch_thread(&mut (*now).lockdep, &(*then).lockdep);
+            Thread::load_address_space(then);
+            stack::sync_tlb();
+            CURRENT[smp::cpu_id()].store(then, Ordering::SeqCst);
+            PREV[smp::cpu_id()].store(now, Ordering::SeqCst);
             _thread_switch(
                 &mut (*now).old_rsp0,
                 (*then).old_rsp0,
                 (*then).kernel_stack.stack_end() as u64,
             );
         }
+        Thread::finish_switch();
+    }
+
+    // Der auf der aufrufenden CPU laufende Thread, null vor dem Start des Schedulers
+    pub fn current() -> *mut Thread {
+        CURRENT[smp::cpu_id()].load(Ordering::SeqCst)
+    }
+
+    // Adressraum des Threads aktivieren (CR3), falls er nicht schon aktiv ist.
+    // Die Kernel-Stacks sind in allen Adressraeumen gleich abgebildet.
+    fn load_address_space(thread_object: *const Thread) {
+        let pml4 = unsafe {
+            match &(*thread_object).address_space {
+                Some(space) => space.get_pml4(),
+                None => paging::kernel_pml4(),
+            }
+        };
+        if paging::active_pml4() != pml4 {
+            cpu::write_cr3(pml4);
+        }
+    }
+
+    // Canaries beider Stacks pruefen, bei einem Ueberlauf gibt es kein Zurueck
+    fn check_stacks(thread_object: *const Thread) {
+        unsafe {
+            if (*thread_object).kernel_stack.canary_ok() == false {
+                panic!("kernel stack overflow in thread {}", *thread_object);
+            }
+            if (*thread_object).user_stack.canary_ok() == false {
+                panic!("user stack overflow in thread {}", *thread_object);
+            }
+        }
+    }
+
+    // Nach dem Umschalten (evtl. auf einer anderen CPU): der verlassene Thread
+    // darf nun von anderen CPUs gewaehlt werden
+    fn finish_switch() {
+        let prev = PREV[smp::cpu_id()].swap(ptr::null_mut(), Ordering::SeqCst);
+        if prev.is_null() == false {
+            unsafe { (*prev).on_cpu.store(false, Ordering::SeqCst) };
+        }
     }
 
     //
@@ -168,7 +458,7 @@ impl Thread {
     // Ring 3 zu versetzen. Dies erfolgt wieder mit einem praeparierten Stack.
     // Hier wird ein Interrupt-Stack-Frame gebaut, sodass beim Ruecksprung
     // mit 'iretq' die Privilegstufe gewechselt wird. Wenn alles klappt
-    // landen wir in der Funktion 'kickoff_user_thread' und sind dann im Ring 3
+    // landen wir in 'call_closure' und sind dann im Ring 3
     // 
     // In den Selektoren RPL = 3, RFLAGS = IOPL=0, IE=1
     //
@@ -179,22 +469,23 @@ impl Thread {
         /*
             Hier muss Code eingefuegt werden
         */
-        let kickoff_user_addr = kickoff_user_thread as *const ();
-        let object: *const Thread = self;
-
-        // "sp0" und "sp3" zeigen ans Ende des jeweiligen Speicherblocks
+        // "sp0" zeigt ans Ende des Kernel-Stacks, der User-Stack wird in 'prepare_user_stack' belegt
         let sp0: *mut u64 = self.kernel_stack.stack_end();
-        let sp3: *mut u64 = self.user_stack.stack_end();
+
+        let (rip, rsp, rdi) = match self.entry.take() {
+            Some(Entry::User(user_entry)) => self.prepare_user_stack(user_entry),
+            _ => panic!("Thread::switch_to_usermode: {} has no user entry", self),
+        };
 
         // Interrupt-Stackframe bauen
         unsafe {
             *sp0 = 0x00DEAD00 as u64;
             *sp0.offset(-1) = ((5 << 3) | 3) as u64;
-            *sp0.offset(-2) = (self.user_stack.stack_end()) as u64;
+            *sp0.offset(-2) = rsp;
             *sp0.offset(-3) = 0x202;
             *sp0.offset(-4) = ((4 << 3) | 3) as u64;
-            *sp0.offset(-5) = kickoff_kernel_thread as u64;
-            *sp0.offset(-6) = object as u64;
+            *sp0.offset(-5) = rip;
+            *sp0.offset(-6) = rdi;
         }
         self.old_rsp0 = (sp0 as u64) - (6*8);
 
@@ -204,13 +495,341 @@ impl Thread {
     }
         // In den Ring 3 schalten -> Aufruf von '_thread_user_start'
 
+    //
+    // User-Stack eines User-Threads praeparieren: oben liegt die Closure,
+    // darunter die Ruecksprungadresse 'user_exit', als waere 'call_closure'
+    // gerufen worden (rsp + 8 ist dann durch 16 teilbar).
+    //
+    // Rueckgabe: rip, rsp und rdi (Zeiger auf die Closure) fuer den Ring 3
+    //
+    fn prepare_user_stack(&mut self, user_entry: UserEntry) -> (u64, u64, u64) {
+        let end = self.user_stack.stack_end() as u64 + consts::STACK_ENTRY_SIZE as u64;
+        let size = user_entry.layout.size() as u64;
+        let align = user_entry.layout.align() as u64;
+        assert!(
+            size + 64 < self.user_stack.get_size() as u64,
+            "Thread: closure does not fit on the user stack"
+        );
+
+        let closure = (end - size) & !(align - 1);
+        let call = user_entry.call;
+        user_entry.move_to(closure as *mut u8);
+
+        let sp = ((closure & !0xf) - 8) as *mut u64;
+        unsafe { *sp = user_exit as *const () as u64 };
+        (call as *const () as u64, sp as u64, closure)
+    }
+
     pub fn get_tid(thread_object: *const Thread) -> usize {
         unsafe { (*thread_object).tid }
     }
 
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
     pub fn get_raw_pointer(&mut self) -> *mut Thread {
         self
     }
+
+    pub fn get_priority(&self) -> usize {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, prio: usize) {
+        assert!(prio < consts::PRIORITY_LEVELS, "Thread::set_priority: invalid priority");
+        self.priority = prio;
+    }
+
+    // Warteschlange, in welcher der Thread blockiert ist (null, falls nicht blockiert)
+    pub fn get_blocked_in(thread_object: *const Thread) -> *const Spinlock<Queue<Box<Thread>>> {
+        unsafe { (*thread_object).blocked_in }
+    }
+
+    pub fn set_blocked_in(thread_object: *mut Thread, wait_queue: *const Spinlock<Queue<Box<Thread>>>) {
+        unsafe { (*thread_object).blocked_in = wait_queue; }
+    }
+
+    // Handle zum Warten auf das Ende des Threads
+    pub fn join_handle(&self) -> JoinHandle {
+        JoinHandle {
+            tid: self.tid,
+            state: self.exit_state.clone(),
+        }
+    }
+
+    //
+    // Exit-Code setzen und alle in 'join' wartenden Threads deblockieren.
+    // Wird von 'Scheduler::exit_with_code' und 'Scheduler::kill' gerufen,
+    // bevor der Thread an den Reaper uebergeben wird (ohne Scheduler-Lock).
+    //
+    pub fn finish(thread_object: *mut Thread, code: i32) {
+        // Noch gehaltene Mutexe werden vergiftet und an Wartende weitergegeben
+        let held = unsafe { core::mem::take(&mut (*thread_object).held_locks) };
+        for lock in held.iter() {
+            lock.release();
+        }
+
+        // Wie 'Mutex::release': mit gesperrter Warteschlange, sonst koennte sich
+        // ein Thread auf einer anderen CPU nach dem Setzen von 'code' noch eintragen
+        let state = unsafe { &(*thread_object).exit_state };
+        let was_enabled = cpu::disable_int_nested();
+        let mut joiners = state.joiners.lock();
+        *state.code.lock() = Some(code);
+        while let Some(j) = joiners.dequeue() {
+            scheduler::deblock(Box::into_raw(j));
+        }
+        drop(joiners);
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    // Mutex oder RwLock 'lock' gehoert nun dem Thread, siehe 'mutex::Mutex'
+    pub fn add_held_lock(thread_object: *mut Thread, lock: HeldLock) {
+        unsafe { (*thread_object).held_locks.push(lock) };
+    }
+
+    // Thread hat das Lock an Adresse 'lock' freigegeben (ein Leser kann mehrere Eintraege haben)
+    pub fn remove_held_lock(thread_object: *mut Thread, lock: *const (), shared: bool) {
+        let held = unsafe { &mut (*thread_object).held_locks };
+        if let Some(pos) = held.iter().position(|l| l.is(lock, shared)) {
+            held.remove(pos);
+        }
+    }
+
+    pub fn get_deferred_next(thread_object: *const Thread) -> *mut Thread {
+        unsafe { (*thread_object).deferred_next }
+    }
+
+    pub fn set_deferred_next(thread_object: *mut Thread, next: *mut Thread) {
+        unsafe { (*thread_object).deferred_next = next };
+    }
+
+    pub fn get_state(&self) -> ThreadState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: ThreadState) {
+        self.state = state;
+    }
+
+    // Wird vom PIT-Interrupt fuer den laufenden Thread gerufen
+    pub fn add_tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    // Momentaufnahme der Verwaltungsdaten, siehe 'scheduler::thread_list'
+    pub fn info(&self) -> ThreadInfo {
+        ThreadInfo {
+            tid: self.tid,
+            name: self.name.clone(),
+            state: self.state,
+            priority: self.priority,
+            is_kernel_thread: self.is_kernel_thread,
+            ticks: self.ticks,
+            switches: self.switches,
+            created: self.created,
+            kernel_stack_used: self.kernel_stack.high_water_mark(),
+            kernel_stack_size: self.kernel_stack.get_size(),
+            user_stack_used: self.user_stack.high_water_mark(),
+            user_stack_size: self.user_stack.get_size(),
+            realtime: self.realtime,
+            cpu: self.cpu,
+        }
+    }
+
+    pub fn get_wakeup_time(&self) -> u64 {
+        self.wakeup_time
+    }
+
+    pub fn set_wakeup_time(&mut self, time: u64) {
+        self.wakeup_time = time;
+    }
+
+    pub fn set_timed_out(&mut self, timed_out: bool) {
+        self.timed_out = timed_out;
+    }
+
+    // Wurde der Thread nach 'Scheduler::prepare_block_until' durch den Timeout geweckt?
+    pub fn take_timed_out(thread_object: *mut Thread) -> bool {
+        unsafe { core::mem::replace(&mut (*thread_object).timed_out, false) }
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    pub fn set_suspended(&mut self, suspended: bool) {
+        self.suspended = suspended;
+    }
+
+    pub fn get_ready_since(&self) -> u64 {
+        self.ready_since
+    }
+
+    pub fn set_ready_since(&mut self, time: u64) {
+        self.ready_since = time;
+    }
+
+    pub fn get_realtime(&self) -> Option<&RealTime> {
+        self.realtime.as_ref()
+    }
+
+    pub fn get_realtime_mut(&mut self) -> Option<&mut RealTime> {
+        self.realtime.as_mut()
+    }
+
+    pub fn is_realtime(thread_object: *const Thread) -> bool {
+        unsafe { (*thread_object).realtime.is_some() }
+    }
+
+    pub fn get_affinity(&self) -> u64 {
+        self.affinity
+    }
+
+    pub fn set_affinity(&mut self, mask: u64) {
+        assert!(mask != 0, "Thread::set_affinity: empty cpu mask");
+        self.affinity = mask;
+    }
+
+    // Darf der Thread auf CPU 'cpu' laufen?
+    pub fn may_run_on(&self, cpu: usize) -> bool {
+        self.affinity & (1 << cpu) != 0
+    }
+
+    pub fn get_cpu(&self) -> usize {
+        self.cpu
+    }
+
+    // Wird vom Scheduler gerufen, wenn der bereite Thread zur Policy von 'cpu' wechselt
+    pub fn set_cpu(&mut self, cpu: usize) {
+        self.cpu = cpu;
+    }
+
+    // Wird vom Scheduler gerufen, wenn der Thread auf 'cpu' aktiv wird
+    pub fn set_on_cpu(&mut self, cpu: usize) {
+        self.cpu = cpu;
+        self.on_cpu.store(true, Ordering::SeqCst);
+    }
+
+    // Laeuft noch eine CPU auf dem Kernel-Stack? Dann darf keine andere CPU den Thread starten.
+    pub fn is_on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::SeqCst)
+    }
+
+    pub fn get_vruntime(&self) -> u64 {
+        self.vruntime
+    }
+
+    pub fn set_vruntime(&mut self, vruntime: u64) {
+        self.vruntime = vruntime;
+    }
+}
+
+/**
+ Description: Snapshot of the statistics of a thread, see `scheduler::thread_list`.
+              `Display` formats one line like `ps`, see `ThreadInfo::HEADER`.
+*/
+#[derive(Clone)]
+pub struct ThreadInfo {
+    pub tid: usize,
+    pub name: String,
+    pub state: ThreadState,
+    pub priority: usize,
+    pub is_kernel_thread: bool,
+    pub ticks: u64,
+    pub switches: u64,
+    pub created: u64,
+    pub kernel_stack_used: usize, // High-Water-Mark in Bytes
+    pub kernel_stack_size: usize,
+    pub user_stack_used: usize,   // 0 bei Kernel-Threads
+    pub user_stack_size: usize,
+    pub realtime: Option<RealTime>,
+    pub cpu: usize, // CPU, auf der der Thread zuletzt lief
+}
+
+impl ThreadInfo {
+    pub const HEADER: &'static str =
+        " TID NAME     CPU STATE    PRIO MODE     TICKS  SWTCH CREATED KSTK KiB USTK KiB";
+}
+
+impl fmt::Display for ThreadInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>4} {:<8.8} {:>3} {:<8} {:>4} {:<6} {:>7} {:>6} {:>7} {:>3}/{:<4} {:>3}/{:<4}",
+            self.tid,
+            self.name,
+            self.cpu,
+            self.state,
+            self.priority,
+            if self.is_kernel_thread { "kernel" } else { "user" },
+            self.ticks,
+            self.switches,
+            self.created,
+            self.kernel_stack_used / 1024,
+            self.kernel_stack_size / 1024,
+            self.user_stack_used / 1024,
+            self.user_stack_size / 1024
+        )
+    }
+}
+
+/**
+ Description: Handle of a thread returned by `Scheduler::ready`. Can be used
+              to wait for the end of the thread and to get its exit code.
+              Remains valid after the thread has been freed by the reaper.
+*/
+pub struct JoinHandle {
+    tid: usize,
+    state: Arc<ExitState>,
+}
+
+impl JoinHandle {
+    pub fn get_tid(&self) -> usize {
+        self.tid
+    }
+
+    /**
+     Description: Check if the thread has terminated (without blocking)
+    */
+    pub fn is_finished(&self) -> bool {
+        self.state.code.lock().is_some()
+    }
+
+    /**
+     Description: Block the calling thread until the thread has terminated.
+
+     Return: \
+        exit code passed to `Scheduler::exit_with_code`, `0` if the entry \
+        function returned or `EXIT_CODE_KILLED` if the thread was killed
+    */
+    pub fn join(&self) -> i32 {
+        if self.tid == scheduler::get_active_tid() {
+            panic!("JoinHandle::join: thread tid={} cannot join itself", self.tid);
+        }
+
+        // Pruefen und Eintragen in die Warteschlange muessen atomar zu 'finish' sein
+        // (auch auf anderen CPUs), daher wie in 'Mutex::lock' mit gesperrtem 'joiners'
+        let was_enabled = cpu::disable_int_nested();
+        let mut joiners = self.state.joiners.lock();
+        let finished = self.state.code.lock().is_some();
+        if finished {
+            drop(joiners);
+        } else {
+            let (curr, next) = scheduler::prepare_block();
+            if curr.is_null() || next.is_null() {
+                panic!("JoinHandle::join: no thread to switch to");
+            }
+            Thread::set_blocked_in(curr, &self.state.joiners);
+            unsafe { joiners.enqueue(Box::from_raw(curr)); }
+            drop(joiners);
+            Thread::switch(curr, next);
+        }
+        cpu::enable_int_nested(was_enabled);
+
+        let code = *self.state.code.lock();
+        code.unwrap()
+    }
 }
 
 // Notwendig, für die Queue-Implementierung im Scheduler
@@ -223,7 +842,198 @@ impl PartialEq for Thread {
 // Notwendig, falls wir die Ready-Queue ausgeben moechten
 impl fmt::Display for Thread {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.tid)
+        write!(f, "{}[tid={}]", self.name, self.tid)
+    }
+}
+
+// Der User-Stack ist im Adressraum eingeblendet ('Stack::drop' gibt die Seiten
+// dort frei), daher vor dem Adressraum freigeben
+impl Drop for Thread {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.user_stack) };
+        self.address_space = None;
+    }
+}
+
+/**
+ Description: Configuration for a new thread. Start with `Builder::new()`,
+              set the wanted options and create the thread with `spawn` \
+              (registers it in the scheduler) or `build`. \
+              Defaults: name "thread-<tid>", kernel thread, `DEFAULT_PRIORITY`,
+              both stacks `consts::STACK_SIZE`, may run on all cpus.
+*/
+pub struct Builder {
+    name: Option<String>,
+    kernel_thread: bool,
+    priority: usize,
+    kernel_stack_size: usize,
+    user_stack_size: usize, // wird fuer Kernel-Threads ignoriert
+    realtime: Option<RealTime>,
+    affinity: u64,
+    address_space: Option<Arc<AddressSpace>>,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder {
+            name: None,
+            kernel_thread: true,
+            priority: consts::DEFAULT_PRIORITY,
+            kernel_stack_size: consts::STACK_SIZE,
+            user_stack_size: consts::STACK_SIZE,
+            realtime: None,
+            affinity: u64::MAX,
+            address_space: None,
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Builder {
+        self.name = Some(String::from(name));
+        self
+    }
+
+    /**
+     Description: Run the thread in ring 3 if `kernel_thread` is `false`, in its
+                  own address space (see `address_space`). In ring 3 only the
+                  code and read-only data of the kernel image and the user
+                  stack are accessible. The closure is copied to the user
+                  stack before the start, captured values must not point
+                  into the heap or kernel data. Closures owning values with
+                  a destructor (e.g. `Box`, `Vec`, `Arc`) are refused with a
+                  panic. Kernel services are only available as system calls,
+                  see `usrlib`.
+    */
+    pub fn kernel_thread(mut self, kernel_thread: bool) -> Builder {
+        self.kernel_thread = kernel_thread;
+        self
+    }
+
+    pub fn priority(mut self, prio: usize) -> Builder {
+        self.priority = prio;
+        self
+    }
+
+    pub fn kernel_stack_size(mut self, size: usize) -> Builder {
+        self.kernel_stack_size = Builder::check_stack_size(size);
+        self
+    }
+
+    pub fn user_stack_size(mut self, size: usize) -> Builder {
+        self.user_stack_size = Builder::check_stack_size(size);
+        self
+    }
+
+    /**
+     Description: Make the thread a periodic real-time thread (times in PIT ticks).
+                  It must be started with `spawn_realtime` and should call
+                  `Scheduler::wait_next_period` at the end of each period.
+    */
+    pub fn realtime(mut self, period: u64, budget: u64, deadline: u64) -> Builder {
+        self.realtime = Some(RealTime::new(period, budget, deadline));
+        self
+    }
+
+    /**
+     Description: Restrict the thread to the cpus in `mask` (bit n = cpu n).
+                  `mask` must contain a running cpu, see `smp::cpu_mask`.
+    */
+    pub fn affinity(mut self, mask: u64) -> Builder {
+        assert!(mask & smp::cpu_mask() != 0, "thread::Builder: no running cpu in mask {:#x}", mask);
+        self.affinity = mask;
+        self
+    }
+
+    /**
+     Description: Run the thread as user thread in the existing address space
+                  `space`, e.g. shared with other user threads, instead of a
+                  new one. Pages made accessible with
+                  `AddressSpace::allow_user` are visible to all its threads.
+    */
+    pub fn address_space(mut self, space: Arc<AddressSpace>) -> Builder {
+        self.kernel_thread = false;
+        self.address_space = Some(space);
+        self
+    }
+
+    // Mindestgroesse pruefen und auf 'STACK_ALIGNMENT' aufrunden
+    fn check_stack_size(size: usize) -> usize {
+        assert!(size >= consts::MIN_STACK_SIZE, "thread::Builder: stack size too small");
+        (size + consts::STACK_ALIGNMENT - 1) & !(consts::STACK_ALIGNMENT - 1)
+    }
+
+    /**
+     Description: Create the thread with closure `f` as entry function.
+                  The tid is taken from `scheduler::next_thread_id`.
+    */
+    pub fn build<F>(self, f: F) -> Box<Thread>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let entry = Entry::new(f, self.kernel_thread);
+        Thread::create(scheduler::next_thread_id(), entry, self)
+    }
+
+    /**
+     Description: Create the thread and register it in the scheduler.
+
+     Return: \
+        handle for waiting on the end of the thread
+    */
+    pub fn spawn<F>(self, f: F) -> JoinHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        scheduler::Scheduler::ready(self.build(f))
+    }
+
+    /**
+     Description: Create a real-time thread and register it in the scheduler,
+                  if it passes the admission control.
+
+     Return: \
+        `None` if the thread has been rejected, the real-time threads could
+        not meet their deadlines any more
+    */
+    pub fn spawn_realtime<F>(self, f: F) -> Option<JoinHandle>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        assert!(self.realtime.is_some(), "thread::Builder: 'realtime' not set");
+        scheduler::Scheduler::ready_realtime(self.build(f))
+    }
+}
+
+/**
+ Description: Create a kernel thread running the closure `f` with default
+              priority and register it in the scheduler.
+
+ Return: \
+    handle for waiting on the end of the thread
+*/
+pub fn spawn<F>(f: F) -> JoinHandle
+where
+    F: FnOnce() + Send + 'static,
+{
+    Builder::new().spawn(f)
+}
+
+/**
+ Description: Same as `spawn` but the closure runs in a user thread (ring 3),
+              see `Builder::kernel_thread`. The closure is copied bitwise to
+              the user stack, hence `F: Copy`.
+*/
+pub fn spawn_user<F>(f: F) -> JoinHandle
+where
+    F: FnOnce() + Copy + Send + 'static,
+{
+    Builder::new().kernel_thread(false).spawn(f)
+}
+
+// Einstiegsfunktion (Closure) aus dem Thread-Objekt nehmen und aufrufen
+fn call_entry(object: *mut Thread) {
+    let entry = unsafe { (*object).entry.take() };
+    if let Some(Entry::Closure(f)) = entry {
+        f();
     }
 }
 
@@ -236,10 +1046,13 @@ impl fmt::Display for Thread {
 //
 #[no_mangle]
 pub extern "C" fn kickoff_kernel_thread(object: *mut Thread) {
+    // Der vorherige Thread dieser CPU ist nun gesichert
+    Thread::finish_switch();
+
     unsafe {
         kprintln!(
-            "kickoff_kernel_thread, tid={}, old_rsp0 = {:x}, is_kernel_thread: {}",
-            (*object).tid,
+            "kickoff_kernel_thread, {}, old_rsp0 = {:x}, is_kernel_thread: {}",
+            *object,
             (*object).old_rsp0,
             (*object).is_kernel_thread
         );
@@ -252,32 +1065,18 @@ pub extern "C" fn kickoff_kernel_thread(object: *mut Thread) {
 
     // Falls dies ein User-Thread ist, schalten wir nun in den User-Mode
     // Der Aufruf kehrt nicht zurueck, schaltet aber IE = 1
-    // Es geht anschliessend in 'kickoff_user_thread' weiter
+    // Es geht anschliessend in 'call_closure' weiter, beendet wird ueber 'user_exit'
     unsafe {
         if (*object).is_kernel_thread == false {
             (*object).switch_to_usermode();
         } else {
             // Interrupts wieder zulassen
             cpu::enable_int();
-            ((*object).entry)();
+            call_entry(object);
         }
     }
-    loop {}
-}
 
-//
-// Dies ist die  Rust-Funktion, die aufgerufen wird, wenn ein
-// Kernel-Thread (Ring 0) in den Ring 3 versetzt wird
-//
-#[no_mangle]
-pub extern "C" fn kickoff_user_thread(object: *mut Thread) {
-    // Einstiegsfunktion des Threads aufrufen
-
-    /*
-        Hier muss Code eingefuegt werden
-    */
-    unsafe {
-        ((*object).entry)();
-    }
-    loop {}
+    // Thread ist fertig, der Reaper gibt den Speicher frei
+    scheduler::Scheduler::exit();
 }
+
@@ -0,0 +1,283 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: channel                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Multi-producer multi-consumer channels for message passing      ║
+   ║         between threads, bounded or unbounded. Built on 'Mutex' and     ║
+   ║         'Condvar'. The channel is closed as soon as all 'Sender's or    ║
+   ║         all 'Receiver's are dropped, waiting threads are woken up then. ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use crate::devices::pit;
+use crate::mylib::condvar::Condvar;
+use crate::mylib::mutex::Mutex;
+
+/**
+ Description: `send` failed, as all receivers are gone. Contains the message.
+*/
+#[derive(Debug, PartialEq)]
+pub struct SendError<T>(pub T);
+
+/**
+ Description: `try_send` failed. Contains the message.
+*/
+#[derive(Debug, PartialEq)]
+pub enum TrySendError<T> {
+    Full(T),         // Kanal ist voll
+    Disconnected(T), // alle Empfaenger sind weg
+}
+
+/**
+ Description: `recv` failed, as the channel is empty and all senders are gone.
+*/
+#[derive(Debug, PartialEq)]
+pub struct RecvError;
+
+/**
+ Description: `try_recv` failed
+*/
+#[derive(Debug, PartialEq)]
+pub enum TryRecvError {
+    Empty,        // keine Nachricht vorhanden
+    Disconnected, // leer und alle Sender sind weg
+}
+
+/**
+ Description: `recv_timeout` failed
+*/
+#[derive(Debug, PartialEq)]
+pub enum RecvTimeoutError {
+    Timeout,      // keine Nachricht innerhalb der Wartezeit
+    Disconnected, // leer und alle Sender sind weg
+}
+
+// Zustand des Kanals, geschuetzt durch die Mutex in 'Shared'
+struct State<T> {
+    buffer: VecDeque<T>,
+    capacity: Option<usize>, // None = unbegrenzt
+    senders: usize,
+    receivers: usize,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        match self.capacity {
+            Some(cap) => self.buffer.len() >= cap,
+            None => false,
+        }
+    }
+}
+
+// Von allen Sendern und Empfaengern gemeinsam genutzt
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar, // wartende Empfaenger
+    not_full: Condvar,  // wartende Sender (nur bei begrenzten Kanaelen)
+}
+
+/**
+ Description: Create a channel buffering at most `capacity` messages.
+              `send` blocks while the channel is full. Panics if
+              `capacity` is 0.
+*/
+pub fn bounded<T: Send>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    if capacity == 0 {
+        panic!("channel::bounded: capacity must not be 0");
+    }
+    channel(Some(capacity))
+}
+
+/**
+ Description: Create a channel without limit, `send` never blocks.
+*/
+pub fn unbounded<T: Send>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+fn channel<T: Send>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::new(),
+            capacity,
+            senders: 1,
+            receivers: 1,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/**
+ Description: Sending side of a channel, may be cloned
+*/
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /**
+     Description: Send `msg`, blocks while a bounded channel is full.
+
+     Return: \
+        `Err` with the message if all receivers are gone
+    */
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if state.receivers == 0 {
+                return Err(SendError(msg));
+            }
+            if state.is_full() == false {
+                break;
+            }
+            state = self.shared.not_full.wait(state);
+        }
+        state.buffer.push_back(msg);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /**
+     Description: Send `msg` without blocking
+
+     Return: \
+        `Err` with the message if the channel is full or all receivers are gone
+    */
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.state.lock();
+        if state.receivers == 0 {
+            return Err(TrySendError::Disconnected(msg));
+        }
+        if state.is_full() {
+            return Err(TrySendError::Full(msg));
+        }
+        state.buffer.push_back(msg);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.senders -= 1;
+        let closed = state.senders == 0;
+        drop(state);
+        if closed {
+            // wartende Empfaenger sollen 'Disconnected' erkennen
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+/**
+ Description: Receiving side of a channel, may be cloned
+*/
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /**
+     Description: Receive a message, blocks while the channel is empty.
+                  Buffered messages are still delivered after all senders
+                  are gone.
+
+     Return: \
+        `Err` if the channel is empty and all senders are gone
+    */
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(msg) = state.buffer.pop_front() {
+                drop(state);
+                self.shared.not_full.notify_one();
+                return Ok(msg);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.shared.not_empty.wait(state);
+        }
+    }
+
+    /**
+     Description: Receive a message without blocking
+    */
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.state.lock();
+        match state.buffer.pop_front() {
+            Some(msg) => {
+                drop(state);
+                self.shared.not_full.notify_one();
+                Ok(msg)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /**
+     Description: Same as `recv`, but waits at most `ticks` PIT ticks.
+    */
+    pub fn recv_timeout(&self, ticks: u64) -> Result<T, RecvTimeoutError> {
+        let deadline = pit::get_systime() + ticks;
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(msg) = state.buffer.pop_front() {
+                drop(state);
+                self.shared.not_full.notify_one();
+                return Ok(msg);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = pit::get_systime();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self.shared.not_empty.wait_timeout(state, deadline - now).0;
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.receivers -= 1;
+        let closed = state.receivers == 0;
+        drop(state);
+        if closed {
+            // wartende Sender sollen 'Disconnected' erkennen
+            self.shared.not_full.notify_all();
+        }
+    }
+}
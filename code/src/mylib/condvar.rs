@@ -0,0 +1,133 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: condvar                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Condition variable used together with 'mylib::mutex::Mutex'. A  ║
+   ║         waiting thread is blocked in the wait queue of the condition    ║
+   ║         variable (registered with 'Thread::set_blocked_in', so it can   ║
+   ║         be killed). 'notify_*' deblocks the threads in FIFO order. A    ║
+   ║         timeout is handled by the timer ISR, see                        ║
+   ║         'Scheduler::prepare_block_until'.                               ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+
+use crate::devices::pit;
+use crate::kernel::cpu;
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::thread::Thread;
+use crate::mylib::mutex::MutexGuard;
+use crate::mylib::queue::Queue;
+use crate::mylib::spinlock::Spinlock;
+
+// Weckzeit von 'wait' ohne Timeout
+const NO_TIMEOUT: u64 = u64::MAX;
+
+/**
+ Description: Condition variable
+*/
+pub struct Condvar {
+    wait_queue: Spinlock<Queue<Box<Thread>>>, // wartende Threads
+}
+
+unsafe impl Sync for Condvar {}
+unsafe impl Send for Condvar {}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar {
+            wait_queue: Spinlock::new(Queue::new()),
+        }
+    }
+
+    /**
+     Description: Free the mutex of `guard` and block until `notify_one` or
+                  `notify_all` is called. The mutex is locked again before
+                  returning. Spurious wake-ups do not happen, but the
+                  condition should be checked in a loop anyway as another
+                  thread may get the mutex first.
+    */
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_until(guard, NO_TIMEOUT).0
+    }
+
+    /**
+     Description: Same as `wait`, but wakes up after `ticks` PIT ticks at the latest.
+
+     Return: \
+        the guard and `true` if the timeout has expired without notification
+    */
+    pub fn wait_timeout<'a, T>(&self, guard: MutexGuard<'a, T>, ticks: u64) -> (MutexGuard<'a, T>, bool) {
+        self.wait_until(guard, pit::get_systime().saturating_add(ticks))
+    }
+
+    //
+    // Der Thread wird eingetragen und blockiert, bevor die Mutex freigegeben
+    // wird. Da die Interrupts dabei gesperrt sind, kann der PIT nicht dazwischen
+    // umschalten und ein 'notify' nach dem Freigeben findet den Thread immer.
+    //
+    fn wait_until<'a, T>(&self, guard: MutexGuard<'a, T>, wakeup: u64) -> (MutexGuard<'a, T>, bool) {
+        let was_enabled = cpu::disable_int_nested();
+        let mutex = MutexGuard::unlocked(guard);
+
+        let mut queue = self.wait_queue.lock();
+        let (curr, next) = if wakeup == NO_TIMEOUT {
+            scheduler::prepare_block()
+        } else {
+            scheduler::prepare_block_until(wakeup)
+        };
+        if curr.is_null() || next.is_null() {
+            panic!("Condvar::wait: no thread to switch to");
+        }
+        Thread::set_blocked_in(curr, &self.wait_queue);
+        unsafe { queue.enqueue(Box::from_raw(curr)) };
+        drop(queue);
+        let mutex = mutex.release();
+        Thread::switch(curr, next);
+
+        // Der Timeout und 'notify_*' holen den Thread mit gesperrter
+        // Warteschlange heraus, es gewinnt also genau einer
+        let timed_out = Thread::take_timed_out(curr);
+        cpu::enable_int_nested(was_enabled);
+        (mutex.lock(), timed_out)
+    }
+
+    /**
+     Description: Wake up the thread waiting longest (if any)
+
+     Return: \
+        `true` if a thread has been woken up
+    */
+    pub fn notify_one(&self) -> bool {
+        let was_enabled = cpu::disable_int_nested();
+        let mut queue = self.wait_queue.lock();
+        let woken = match queue.dequeue() {
+            Some(that) => {
+                scheduler::deblock(Box::into_raw(that));
+                true
+            }
+            None => false,
+        };
+        drop(queue);
+        cpu::enable_int_nested(was_enabled);
+        woken
+    }
+
+    /**
+     Description: Wake up all waiting threads
+
+     Return: \
+        number of threads woken up
+    */
+    pub fn notify_all(&self) -> usize {
+        let was_enabled = cpu::disable_int_nested();
+        let mut queue = self.wait_queue.lock();
+        let mut count = 0;
+        while let Some(that) = queue.dequeue() {
+            scheduler::deblock(Box::into_raw(that));
+            count += 1;
+        }
+        drop(queue);
+        cpu::enable_int_nested(was_enabled);
+        count
+    }
+}
@@ -1,16 +1,8 @@
+use crate::kernel::threads::scheduler::Scheduler;
 
 
-use crate::devices::pit;
-
-
+// Block the calling thread for `ticks` PIT ticks (10ms each)
+// without burning its time slice, see 'Scheduler::sleep'
 pub fn delay(ticks: u64) {
-   let start_time = pit::get_systime();
-   let mut actual_time;
-
-   loop {
-      actual_time = pit::get_systime();
-      if actual_time - start_time > ticks {
-         break;
-      }
-   }
+   Scheduler::sleep(ticks);
 }
@@ -4,3 +4,8 @@ pub mod queue;
 pub mod spinlock;
 pub mod delay;
 pub mod mutex;
+pub mod semaphore;
+pub mod condvar;
+pub mod rwlock;
+pub mod channel;
+pub mod usrlib;
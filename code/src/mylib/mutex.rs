@@ -4,95 +4,325 @@
    ║ Descr.: Mutex with wait_queue. It will block threads calling 'lock', if ║
    ║         the lock is already held by another thread. When the lock is    ║
    ║         freed a waiting thread is deblocked (put into ready queue).     ║
+   ║         The mutex owns the data it protects, like 'std::sync::Mutex'.   ║
+   ║         If the owner terminates while holding the lock, the mutex is    ║
+   ║         poisoned and handed to the next waiting thread.                 ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoetter, Univ. Duesseldorf, 13.6.2024                 ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 
 use alloc::boxed::Box;
-use core::ptr::{self, null};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use crate::kernel::{cpu, interrupts};
-use crate::kernel::threads::scheduler::{self, prepare_block, Scheduler};
+use crate::kernel::cpu;
+use crate::kernel::lockdep::{self, LockClass};
+use crate::kernel::threads::scheduler::{self, prepare_block};
 use crate::kernel::threads::thread::Thread;
 use crate::mylib::queue::Queue;
 use crate::mylib::spinlock::Spinlock;
 
+// Wert von 'owner', solange die Mutex frei ist
+const NO_OWNER: usize = usize::MAX;
 
 /**
- Description: Mutex
-*/pub struct Mutex {
-    lock: AtomicBool,
+ Description: Mutex protecting data of type `T`
+*/
+pub struct Mutex<T> {
+    owner: AtomicUsize,    // tid des Besitzers oder NO_OWNER
+    poisoned: AtomicBool,  // Besitzer wurde beendet, waehrend er die Mutex hielt
     wait_queue: Spinlock<Queue<Box<Thread>>>, // blockierte Threads
+    class: LockClass,      // Lock-Klasse fuer 'lockdep', eine je Aufrufstelle von 'new'
+    data: UnsafeCell<T>,
 }
 
 // Gleiche unsafe Implementierung wie in 'std::sync::Mutex'
-unsafe impl Sync for Mutex {}
-unsafe impl Send for Mutex {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+/**
+ Description: Mutex (or `RwLock`) held by a thread, recorded in the thread
+              (see `Thread::add_held_lock`) to release it if the thread
+              terminates.
+*/
+pub struct HeldLock {
+    lock: *const (),
+    shared: bool,              // Leser eines 'RwLock'
+    owner_died: fn(*const ()), // z.B. 'Mutex::<T>::owner_died' fuer das passende T
+}
 
-impl Mutex {
-    pub const fn new() -> Mutex {
-			
-	/* Hier muss Code eingefuegt werden */
-    return Mutex {
-        lock: AtomicBool::new(false),
-        wait_queue: Spinlock::new(Queue::new()),
-    };
+impl HeldLock {
+    /**
+     Description: Entry for the lock at address `lock`, `owner_died` is
+                  called with `lock` if the thread terminates.
 
+     Parameters: \
+        `shared` `true` for shared (read) access
+    */
+    pub fn new(lock: *const (), shared: bool, owner_died: fn(*const ())) -> HeldLock {
+        HeldLock { lock, shared, owner_died }
+    }
+
+    /**
+     Description: Check if this entry is for the lock at address `lock` with
+                  shared or exclusive access
+    */
+    pub fn is(&self, lock: *const (), shared: bool) -> bool {
+        self.lock == lock && self.shared == shared
+    }
+
+    /**
+     Description: The owner terminated, poison and release the mutex
+    */
+    pub fn release(&self) {
+        (self.owner_died)(self.lock);
+    }
+}
+
+impl<T> Mutex<T> {
+    /**
+     Description: Create a free mutex. All mutexes created at the same code
+                  location share a lock class for `lockdep`, see
+                  `LockClass::at`.
+    */
+    #[track_caller]
+    pub const fn new(data: T) -> Mutex<T> {
+        Mutex {
+            owner: AtomicUsize::new(NO_OWNER),
+            poisoned: AtomicBool::new(false),
+            wait_queue: Spinlock::named("Mutex::wait_queue", Queue::new()),
+            class: LockClass::at("Mutex", Location::caller()),
+            data: UnsafeCell::new(data),
+        }
     }
 
     /**
-     Description: Get the mutex.
+     Description: Get the mutex, blocks the calling thread if it is held by
+                  another thread. Panics if the caller holds it already.
+                  If a previous owner terminated while holding the mutex, the
+                  data may be inconsistent, see `is_poisoned`.
     */
-    pub fn lock(&self) -> MutexGuard {
-		/* Hier muss Code eingefuegt werden */
-        let l = self.lock.load(Ordering::SeqCst);
-        if l {
-            let was_enabled = cpu::disable_int_nested();
-            let (curr, next) = prepare_block();
-            if curr == ptr::null_mut() || next == ptr::null_mut() || curr == next {
-                panic!("No threads to switch to");
-            } else {
-                unsafe {self.wait_queue.lock().enqueue(Box::from_raw(curr)); }
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        lockdep::might_block("Mutex::lock");
+        let me = scheduler::get_active_tid();
+        let was_enabled = cpu::disable_int_nested();
+
+        // Reihenfolge vor dem Blockieren pruefen, danach waere es zu spaet. Auch
+        // vor 'wait_queue', sonst ergaebe das Sperren zweier Mutexe derselben
+        // Klasse einen Zyklus 'Mutex -> wait_queue -> Mutex'.
+        lockdep::acquire(&self.class);
+
+        // Pruefen und Eintragen in die Warteschlange muessen atomar zu 'unlock' sein
+        let mut queue = self.wait_queue.lock();
+        match self.owner.compare_exchange(NO_OWNER, me, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => {
+                drop(queue);
+                Thread::add_held_lock(scheduler::get_active(), self.held_lock());
+            }
+            Err(owner) if owner == me => {
+                drop(queue);
+                panic!("Mutex::lock: thread tid={} already holds the mutex", me);
+            }
+            Err(_) => {
+                let (curr, next) = prepare_block();
+                if curr.is_null() || next.is_null() || curr == next {
+                    panic!("No threads to switch to");
+                }
+                Thread::set_blocked_in(curr, &self.wait_queue);
+                unsafe { queue.enqueue(Box::from_raw(curr)); }
+                drop(queue);
                 Thread::switch(curr, next);
-           }
-           cpu::enable_int_nested(was_enabled);
-        } else {
-            self.lock.store(true, Ordering::SeqCst);
+                // 'release' hat uns die Mutex uebergeben
+            }
         }
-        MutexGuard { lock: self }
+        cpu::enable_int_nested(was_enabled);
+        self.guard()
     }
 
     /**
-     Description: Free the mutex. Called from `drop` in the `MutexGuard`
+     Description: Get the mutex without blocking. Panics if the caller holds it already.
+
+     Return: \
+        `None` if the mutex is held by another thread
+    */
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let me = scheduler::get_active_tid();
+        match self.owner.compare_exchange(NO_OWNER, me, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => {
+                lockdep::acquired_try(&self.class);
+                Thread::add_held_lock(scheduler::get_active(), self.held_lock());
+                Some(self.guard())
+            }
+            Err(owner) if owner == me => {
+                panic!("Mutex::try_lock: thread tid={} already holds the mutex", me)
+            }
+            Err(_) => None,
+        }
+    }
+
+    // Eintrag fuer die Liste der gehaltenen Mutexe des Besitzers
+    fn held_lock(&self) -> HeldLock {
+        HeldLock::new(self as *const Self as *const (), false, Mutex::<T>::owner_died)
+    }
+
+    fn guard(&self) -> MutexGuard<'_, T> {
+        MutexGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /**
+     Description: Check if a thread terminated while holding the mutex
+    */
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::SeqCst)
+    }
+
+    /**
+     Description: Mark the data as consistent again after a poisoning
+    */
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::SeqCst);
+    }
+
+    /**
+     Description: Tid of the thread holding the mutex, `None` if it is free
+    */
+    pub fn get_owner(&self) -> Option<usize> {
+        match self.owner.load(Ordering::SeqCst) {
+            NO_OWNER => None,
+            tid => Some(tid),
+        }
+    }
+
+    /**
+     Description: Free the mutex. Called from `drop` in the `MutexGuard`.
+                  Panics if the calling thread is not the owner.
     */
     fn unlock(&self) {
-		/* Hier muss Code eingefuegt werden */
-        if let Some(q) = self.wait_queue.lock().dequeue(){
-            scheduler::deblock(Box::into_raw(q));
+        self.disown();
+        self.release();
+    }
+
+    // Besitz des aufrufenden Threads beenden, die Mutex ist danach aber noch belegt
+    fn disown(&self) {
+        let me = scheduler::get_active_tid();
+        if self.owner.load(Ordering::SeqCst) != me {
+            panic!("Mutex::unlock: thread tid={} is not the owner", me);
+        }
+        Thread::remove_held_lock(scheduler::get_active(), self as *const Self as *const (), false);
+        lockdep::release(&self.class);
+    }
+
+    // Mutex dem ersten wartenden Thread uebergeben oder freigeben. Der neue
+    // Besitzer wird sofort eingetragen, falls er beendet wird, bevor er laeuft.
+    // Nur nach 'disown' bzw. fuer einen beendeten Besitzer, siehe 'UnlockedMutex'.
+    fn release(&self) {
+        let was_enabled = cpu::disable_int_nested();
+        let mut queue = self.wait_queue.lock();
+        if let Some(next) = queue.dequeue() {
+            let next = Box::into_raw(next);
+            self.owner.store(Thread::get_tid(next), Ordering::SeqCst);
+            Thread::add_held_lock(next, self.held_lock());
+            scheduler::deblock(next);
         } else {
-            self.lock.store(false, Ordering::SeqCst);
+            self.owner.store(NO_OWNER, Ordering::SeqCst);
         }
+        drop(queue);
+        cpu::enable_int_nested(was_enabled);
+    }
 
+    // Besitzer wurde beendet (siehe 'Thread::finish'), 'lock' zeigt auf eine Mutex<T>
+    fn owner_died(lock: *const ()) {
+        let mutex = unsafe { &*(lock as *const Mutex<T>) };
+        mutex.poisoned.store(true, Ordering::SeqCst);
+        mutex.release();
+    }
+}
+
+/**
+Description: Mutex guard used by Mutex to automatically call `unlock`
+             for the mutex in case the guard is dropped. It also provides
+             access to the data protected by the mutex. The guard must be
+             dropped by the thread which locked the mutex.
+*/
+pub struct MutexGuard<'a, T> {
+    lock: &'a Mutex<T>,
+    _not_send: PhantomData<*const ()>, // nur der Besitzer darf freigeben
+}
 
+impl<'a, T> MutexGuard<'a, T> {
+    /**
+     Description: Give up the ownership without freeing the mutex, used by `Condvar`
+                  to block before the mutex is freed. The mutex is freed with
+                  `UnlockedMutex::release` or when the result is dropped.
+    */
+    pub fn unlocked(guard: MutexGuard<'a, T>) -> UnlockedMutex<'a, T> {
+        let lock = guard.lock;
+        core::mem::forget(guard);
+        lock.disown();
+        UnlockedMutex { lock }
     }
+}
+
+/**
+Description: Mutex whose owner gave up the ownership with `MutexGuard::unlocked`,
+             but which is not free yet. No thread owns it, only this object can
+             free it.
+*/
+pub struct UnlockedMutex<'a, T> {
+    lock: &'a Mutex<T>,
+}
+
+impl<'a, T> UnlockedMutex<'a, T> {
+    /**
+     Description: Hand the mutex to the first waiting thread or free it
+
+     Return: \
+        the mutex, e.g. to lock it again
+    */
+    pub fn release(self) -> &'a Mutex<T> {
+        let lock = self.lock;
+        drop(self);
+        lock
+    }
+}
 
+impl<'a, T> Drop for UnlockedMutex<'a, T> {
+    fn drop(&mut self) {
+        self.lock.release();
+    }
 }
 
 /**
-Description: Mutex guard used by Mutex to automatically call `unlock`
-             for the mutex in case the guard is dropped.
+Description: Implementation for `as_ref()`
 */
-pub struct MutexGuard<'a> {
-    lock: &'a Mutex,
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
 }
 
+/**
+Description: Implementation for `as_mut()`
+*/
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
 
 /**
 Description: Implementation for `drop()` which will call `unlock` on the mutex
 */
-impl<'a> Drop for MutexGuard<'a> {
+impl<'a, T> Drop for MutexGuard<'a, T> {
     fn drop(&mut self) {
         self.lock.unlock();
     }
@@ -1,5 +1,5 @@
 use alloc::rc::Rc;
-use core::cell::RefCell;
+use core::cell::{Ref, RefCell};
 use core::fmt;
 use core::fmt::Display;
 
@@ -86,6 +86,56 @@ impl<T: PartialEq> Queue<T> {
         }
         return false;
     }
+
+    // Referenz auf das Element am Kopf der Liste, ohne es auszuhaengen
+    pub fn peek(&self) -> Option<Ref<'_, T>> {
+        self.head
+            .as_ref()
+            .map(|n| Ref::map(n.borrow(), |node| &node.data))
+    }
+
+    // Pruefen, ob die Liste leer ist
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    // Anzahl der Elemente in der Liste
+    pub fn len(&self) -> usize {
+        let mut count = 0;
+        let mut node = self.head.clone();
+        while let Some(n) = node {
+            count += 1;
+            node = n.borrow().next.clone();
+        }
+        count
+    }
+
+    // Suche das erste Element, fuer das 'pred' true liefert, haenge es aus
+    // und gib es zurueck (im Gegensatz zu 'remove' wird das Element nicht
+    // geloescht, sondern an den Aufrufer uebergeben)
+    pub fn remove_first<F: Fn(&T) -> bool>(&mut self, pred: F) -> Option<T> {
+        let mut node = self.head.clone();
+        let mut prev: Option<Rc<RefCell<Node<T>>>> = None;
+
+        while let Some(n) = node {
+            if pred(&n.borrow().data) {
+                let next = n.borrow_mut().next.take();
+                match prev {
+                    Some(p) => {
+                        p.borrow_mut().next = next;
+                    }
+                    None => {
+                        self.head = next;
+                    }
+                }
+                return Some(Rc::try_unwrap(n).ok().unwrap().into_inner().data);
+            }
+            let next = n.borrow().next.clone();
+            prev = Some(n);
+            node = next;
+        }
+        None
+    }
 }
 
 // Ausgabe der Liste
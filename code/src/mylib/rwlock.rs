@@ -0,0 +1,389 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: rwlock                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Blocking reader-writer lock. Many readers or one writer may     ║
+   ║         hold the lock. Writers are preferred: as soon as a writer waits ║
+   ║         new readers are blocked. The lock is handed over directly to    ║
+   ║         the deblocked threads (like 'Mutex'), a woken up thread owns it ║
+   ║         already. Like a 'Mutex' the lock is recorded in the holding     ║
+   ║         threads and released if a holder terminates.                   ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+
+use crate::kernel::cpu;
+use crate::kernel::lockdep::{self, LockClass};
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::thread::Thread;
+use crate::mylib::mutex::HeldLock;
+use crate::mylib::queue::Queue;
+use crate::mylib::spinlock::{Spinlock, SpinlockGuard};
+
+// Belegung der Sperre
+struct RwState {
+    readers: usize, // Anzahl der Leser (inkl. eines wartenden 'upgrade')
+    writer: bool,   // ein Schreiber haelt die Sperre
+}
+
+/**
+ Description: Reader-writer lock protecting data of type `T`
+*/
+pub struct RwLock<T> {
+    state: Spinlock<RwState>,
+    read_queue: Spinlock<Queue<Box<Thread>>>,    // blockierte Leser
+    write_queue: Spinlock<Queue<Box<Thread>>>,   // blockierte Schreiber
+    upgrade_queue: Spinlock<Queue<Box<Thread>>>, // hoechstens ein Leser in 'upgrade'
+    class: LockClass, // Lock-Klasse fuer 'lockdep', eine je Aufrufstelle von 'new'
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+unsafe impl<T: Send> Send for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /**
+     Description: Create a free lock. All locks created at the same code
+                  location share a lock class for `lockdep` (readers and
+                  writers alike), see `LockClass::at`.
+    */
+    #[track_caller]
+    pub const fn new(data: T) -> RwLock<T> {
+        RwLock {
+            state: Spinlock::named("RwLock::state", RwState {
+                readers: 0,
+                writer: false,
+            }),
+            read_queue: Spinlock::named("RwLock::read_queue", Queue::new()),
+            write_queue: Spinlock::named("RwLock::write_queue", Queue::new()),
+            upgrade_queue: Spinlock::named("RwLock::upgrade_queue", Queue::new()),
+            class: LockClass::at("RwLock", Location::caller()),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    // Duerfen neue Leser die Sperre bekommen? Nicht, wenn ein Schreiber
+    // sie haelt oder auf sie wartet (Schreiber werden bevorzugt).
+    fn readable(&self, state: &RwState) -> bool {
+        !state.writer
+            && self.write_queue.lock().is_empty()
+            && self.upgrade_queue.lock().is_empty()
+    }
+
+    /**
+     Description: Get shared (read) access, blocks while a writer holds the
+                  lock or is waiting for it.
+    */
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        lockdep::might_block("RwLock::read");
+        lockdep::acquire(&self.class);
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = self.state.lock();
+        if self.readable(&state) {
+            state.readers += 1;
+            Thread::add_held_lock(scheduler::get_active(), self.held_lock(true));
+            drop(state);
+        } else {
+            block(&self.read_queue, state);
+        }
+        cpu::enable_int_nested(was_enabled);
+        self.read_guard()
+    }
+
+    /**
+     Description: Get exclusive (write) access, blocks while other threads
+                  hold the lock.
+    */
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        lockdep::might_block("RwLock::write");
+        lockdep::acquire(&self.class);
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = self.state.lock();
+        if !state.writer && state.readers == 0 {
+            state.writer = true;
+            Thread::add_held_lock(scheduler::get_active(), self.held_lock(false));
+            drop(state);
+        } else {
+            block(&self.write_queue, state);
+        }
+        cpu::enable_int_nested(was_enabled);
+        self.write_guard()
+    }
+
+    /**
+     Description: Get shared access without blocking
+
+     Return: \
+        `None` if a writer holds the lock or is waiting for it
+    */
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = self.state.lock();
+        let ok = self.readable(&state);
+        if ok {
+            state.readers += 1;
+            Thread::add_held_lock(scheduler::get_active(), self.held_lock(true));
+        }
+        drop(state);
+        cpu::enable_int_nested(was_enabled);
+        if ok {
+            lockdep::acquired_try(&self.class);
+            Some(self.read_guard())
+        } else {
+            None
+        }
+    }
+
+    /**
+     Description: Get exclusive access without blocking
+
+     Return: \
+        `None` if the lock is held by other threads
+    */
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = self.state.lock();
+        let ok = !state.writer && state.readers == 0;
+        if ok {
+            state.writer = true;
+            Thread::add_held_lock(scheduler::get_active(), self.held_lock(false));
+        }
+        drop(state);
+        cpu::enable_int_nested(was_enabled);
+        if ok {
+            lockdep::acquired_try(&self.class);
+            Some(self.write_guard())
+        } else {
+            None
+        }
+    }
+
+    // Eintrag fuer die Liste der gehaltenen Locks eines Lesers oder Schreibers
+    fn held_lock(&self, shared: bool) -> HeldLock {
+        let died = if shared { RwLock::<T>::reader_died } else { RwLock::<T>::writer_died };
+        HeldLock::new(self as *const Self as *const (), shared, died)
+    }
+
+    // Eintrag in der Liste der gehaltenen Locks von 'thread' austauschen (upgrade, downgrade)
+    fn change_held_lock(&self, thread: *mut Thread, shared: bool) {
+        Thread::remove_held_lock(thread, self as *const Self as *const (), !shared);
+        Thread::add_held_lock(thread, self.held_lock(shared));
+    }
+
+    // Ein Leser wurde beendet (siehe 'Thread::finish'), auch waehrend 'upgrade'
+    fn reader_died(lock: *const ()) {
+        let rwlock = unsafe { &*(lock as *const RwLock<T>) };
+        rwlock.read_unlock();
+    }
+
+    fn writer_died(lock: *const ()) {
+        let rwlock = unsafe { &*(lock as *const RwLock<T>) };
+        rwlock.write_unlock();
+    }
+
+    fn read_guard(&self) -> RwLockReadGuard<'_, T> {
+        RwLockReadGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    fn write_guard(&self) -> RwLockWriteGuard<'_, T> {
+        RwLockWriteGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    // Leser gibt die Sperre frei, ggf. bekommt ein wartendes 'upgrade' oder
+    // der naechste Schreiber die Sperre. Der Eintrag in der Liste der
+    // gehaltenen Locks ist schon entfernt.
+    fn read_unlock(&self) {
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = self.state.lock();
+        state.readers -= 1;
+        if state.readers == 1 {
+            // nur noch der Leser in 'upgrade' uebrig?
+            let upgrader = self.upgrade_queue.lock().dequeue();
+            if let Some(t) = upgrader {
+                state.readers = 0;
+                state.writer = true;
+                let t = Box::into_raw(t);
+                self.change_held_lock(t, false);
+                scheduler::deblock(t);
+            }
+        } else if state.readers == 0 {
+            self.grant(&mut state);
+        }
+        drop(state);
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    fn write_unlock(&self) {
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = self.state.lock();
+        state.writer = false;
+        self.grant(&mut state);
+        drop(state);
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    // Die Sperre ist frei: zuerst ein wartender Schreiber, sonst alle wartenden Leser
+    fn grant(&self, state: &mut RwState) {
+        let writer = self.write_queue.lock().dequeue();
+        if let Some(t) = writer {
+            state.writer = true;
+            let t = Box::into_raw(t);
+            Thread::add_held_lock(t, self.held_lock(false));
+            scheduler::deblock(t);
+            return;
+        }
+        self.wake_readers(state);
+    }
+
+    // Alle wartenden Leser erhalten die Sperre
+    fn wake_readers(&self, state: &mut RwState) {
+        loop {
+            let reader = self.read_queue.lock().dequeue();
+            match reader {
+                Some(t) => {
+                    state.readers += 1;
+                    let t = Box::into_raw(t);
+                    Thread::add_held_lock(t, self.held_lock(true));
+                    scheduler::deblock(t);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+// Aufrufenden Thread in 'queue' blockieren. 'state' wird erst freigegeben, wenn
+// der Thread eingetragen ist, damit kein Freigeben verloren geht.
+fn block(queue: &Spinlock<Queue<Box<Thread>>>, state: SpinlockGuard<RwState>) {
+    let (curr, next) = scheduler::prepare_block();
+    if curr.is_null() || next.is_null() || curr == next {
+        panic!("RwLock: no thread to switch to");
+    }
+    Thread::set_blocked_in(curr, queue);
+    unsafe { queue.lock().enqueue(Box::from_raw(curr)) };
+    drop(state);
+    Thread::switch(curr, next);
+    // der Thread, der uns deblockiert hat, hat uns die Sperre uebergeben
+}
+
+/**
+Description: Shared access to the data of a `RwLock`, released when dropped
+*/
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T> RwLockReadGuard<'a, T> {
+    /**
+     Description: Turn the shared access into exclusive access. Blocks until
+                  all other readers are gone, new readers are blocked meanwhile.
+                  Panics if another reader is already waiting in `upgrade`,
+                  as both would wait for each other forever. If the thread
+                  is killed while waiting, its read access is released.
+    */
+    pub fn upgrade(guard: RwLockReadGuard<'a, T>) -> RwLockWriteGuard<'a, T> {
+        // die Lock-Klasse bleibt in 'lockdep' gehalten
+        lockdep::might_block("RwLockReadGuard::upgrade");
+        let lock = guard.lock;
+        core::mem::forget(guard);
+
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = lock.state.lock();
+        if state.readers == 1 {
+            state.readers = 0;
+            state.writer = true;
+            lock.change_held_lock(scheduler::get_active(), false);
+            drop(state);
+        } else {
+            if !lock.upgrade_queue.lock().is_empty() {
+                drop(state);
+                panic!("RwLockReadGuard::upgrade: another reader is upgrading (deadlock)");
+            }
+            block(&lock.upgrade_queue, state);
+        }
+        cpu::enable_int_nested(was_enabled);
+        lock.write_guard()
+    }
+}
+
+impl<'a, T> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        let lock = self.lock as *const RwLock<T> as *const ();
+        Thread::remove_held_lock(scheduler::get_active(), lock, true);
+        lockdep::release(&self.lock.class);
+        self.lock.read_unlock();
+    }
+}
+
+/**
+Description: Exclusive access to the data of a `RwLock`, released when dropped
+*/
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T> RwLockWriteGuard<'a, T> {
+    /**
+     Description: Turn the exclusive access into shared access without
+                  releasing the lock. Waiting readers get the lock, too,
+                  unless a writer is waiting.
+    */
+    pub fn downgrade(guard: RwLockWriteGuard<'a, T>) -> RwLockReadGuard<'a, T> {
+        let lock = guard.lock;
+        core::mem::forget(guard);
+
+        let was_enabled = cpu::disable_int_nested();
+        let mut state = lock.state.lock();
+        state.writer = false;
+        state.readers = 1;
+        lock.change_held_lock(scheduler::get_active(), true);
+        if lock.write_queue.lock().is_empty() {
+            lock.wake_readers(&mut state);
+        }
+        drop(state);
+        cpu::enable_int_nested(was_enabled);
+        lock.read_guard()
+    }
+}
+
+impl<'a, T> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        let lock = self.lock as *const RwLock<T> as *const ();
+        Thread::remove_held_lock(scheduler::get_active(), lock, false);
+        lockdep::release(&self.lock.class);
+        self.lock.write_unlock();
+    }
+}
@@ -0,0 +1,130 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: semaphore                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Counting semaphore with a FIFO wait queue. 'p' blocks the       ║
+   ║         calling thread while the counter is 0, 'v' hands the counter    ║
+   ║         directly to the first waiting thread. 'v' may be called from an ║
+   ║         ISR, e.g. to signal a consumer thread waiting for input.        ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::kernel::cpu;
+use crate::kernel::lockdep;
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::thread::Thread;
+use crate::mylib::queue::Queue;
+use crate::mylib::spinlock::Spinlock;
+
+/**
+ Description: Counting semaphore
+*/
+pub struct Semaphore {
+    count: AtomicUsize, // wird nur mit gesperrter 'wait_queue' veraendert
+    wait_queue: Spinlock<Queue<Box<Thread>>>, // blockierte Threads
+}
+
+unsafe impl Sync for Semaphore {}
+unsafe impl Send for Semaphore {}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Semaphore {
+        Semaphore {
+            count: AtomicUsize::new(count),
+            wait_queue: Spinlock::new(Queue::new()),
+        }
+    }
+
+    /**
+     Description: Decrement the counter. Blocks the calling thread while it is 0.
+                  Must not be called from an ISR.
+    */
+    pub fn p(&self) {
+        lockdep::might_block("Semaphore::p");
+        // Die Warteschlange wird auch in ISRs gesperrt ('v'), daher ohne Interrupts
+        let was_enabled = cpu::disable_int_nested();
+        let mut queue = self.wait_queue.lock();
+        let count = self.count.load(Ordering::SeqCst);
+        if count > 0 {
+            self.count.store(count - 1, Ordering::SeqCst);
+            drop(queue);
+        } else {
+            let (curr, next) = scheduler::prepare_block();
+            if curr.is_null() || next.is_null() || curr == next {
+                panic!("Semaphore::p: no thread to switch to");
+            }
+            Thread::set_blocked_in(curr, &self.wait_queue);
+            unsafe { queue.enqueue(Box::from_raw(curr)) };
+            drop(queue);
+            Thread::switch(curr, next);
+            // 'v' hat den Zaehler an uns weitergegeben
+        }
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    /**
+     Description: Same as `p`
+    */
+    pub fn wait(&self) {
+        self.p();
+    }
+
+    /**
+     Description: Decrement the counter if it is not 0, without blocking
+
+     Return: \
+        `false` if the counter was 0
+    */
+    pub fn try_wait(&self) -> bool {
+        let was_enabled = cpu::disable_int_nested();
+        let queue = self.wait_queue.lock();
+        let count = self.count.load(Ordering::SeqCst);
+        if count > 0 {
+            self.count.store(count - 1, Ordering::SeqCst);
+        }
+        drop(queue);
+        cpu::enable_int_nested(was_enabled);
+        count > 0
+    }
+
+    /**
+     Description: Increment the counter or, if threads are waiting, deblock
+                  the first one instead. May be called from an ISR.
+    */
+    pub fn v(&self) {
+        let was_enabled = cpu::disable_int_nested();
+        let mut queue = self.wait_queue.lock();
+        match queue.dequeue() {
+            Some(that) => {
+                drop(queue);
+                // Mit gesperrten Interrupts (z.B. im ISR) kann der unterbrochene
+                // Code den Scheduler gesperrt haben
+                if was_enabled == false {
+                    scheduler::deblock_from_isr(Box::into_raw(that));
+                } else {
+                    scheduler::deblock(Box::into_raw(that));
+                }
+            }
+            None => {
+                self.count.fetch_add(1, Ordering::SeqCst);
+                drop(queue);
+            }
+        }
+        cpu::enable_int_nested(was_enabled);
+    }
+
+    /**
+     Description: Same as `v`
+    */
+    pub fn signal(&self) {
+        self.v();
+    }
+
+    /**
+     Description: Current value of the counter
+    */
+    pub fn get_count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
@@ -1,7 +1,9 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: spinlock                                                        ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Descr.: Basic generic spinlock using atomics.                           ║
+   ║ Descr.: Basic generic spinlock using atomics. Checked by 'lockdep': all ║
+   ║         spinlocks created at the same code location (and with the same  ║
+   ║         name) share a lock class.                                       ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoetter, Univ. Duesseldorf, 13.6.2024                 ║
    ╚═════════════════════════════════════════════════════════════════════════╝
@@ -10,13 +12,17 @@
 use core::arch::asm;
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
+use core::panic::Location;
 use core::sync::atomic::{AtomicBool, Ordering};
 
+use crate::kernel::lockdep::{self, LockClass};
+
 /**
  Description: Spinlock
 */
 pub struct Spinlock<T: ?Sized> {
     lock: AtomicBool, // false = lock is not set, true = lock is set
+    class: LockClass, // Lock-Klasse fuer 'lockdep', eine je Aufrufstelle von 'new'
     data: UnsafeCell<T>, // unsafe to allow mutable access through non-mutable ref (see SpinlockGuard)
 }
 
@@ -25,9 +31,22 @@ unsafe impl<T> Sync for Spinlock<T> where T: Send {}
 unsafe impl<T> Send for Spinlock<T> where T: Send {}
 
 impl<T> Spinlock<T> {
+    #[track_caller]
     pub const fn new(data: T) -> Self {
+        Spinlock::named("Spinlock", data)
+    }
+
+    /**
+     Description: Create a spinlock with the lock class `name` at the calling
+                  code location. Needed if a `#[track_caller]` constructor
+                  creates several spinlocks, they would share one class and
+                  nesting them would be reported as recursive locking.
+    */
+    #[track_caller]
+    pub const fn named(name: &'static str, data: T) -> Self {
         Spinlock {
             lock: AtomicBool::new(false),
+            class: LockClass::at(name, Location::caller()),
             data: UnsafeCell::new(data),
         }
     }
@@ -35,7 +54,8 @@ impl<T> Spinlock<T> {
     /**
      Description: Spin until we successfully acquire the lock
     */
-    pub fn lock(&self) -> SpinlockGuard<T> {
+    pub fn lock(&self) -> SpinlockGuard<'_, T> {
+        lockdep::acquire(&self.class);
         loop {
             let res = self
                 .lock
@@ -51,13 +71,32 @@ impl<T> Spinlock<T> {
 
         // Return a SpinlockGuard which will allow mutable access to 'data'
         // and call 'unlock' if it is dropped.
-        SpinlockGuard { lock: &self }
+        SpinlockGuard { lock: self }
+    }
+
+    /**
+     Description: Try to acquire the lock without spinning
+
+     Return: \
+        `None` if the lock is set
+    */
+    pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+        let res = self
+            .lock
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst);
+        if res.is_ok() {
+            lockdep::acquired_try(&self.class);
+            Some(SpinlockGuard { lock: self })
+        } else {
+            None
+        }
     }
 
     /**
      Description: Free the spinlock. Called from `drop` in the `SpinlockGuard`
     */
     fn unlock(&self) {
+        lockdep::release(&self.class);
         self.lock.store(false, Ordering::SeqCst);
     }
 }
@@ -0,0 +1,67 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: usrlib                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Functions for user threads (ring 3), each is a system call with ║
+   ║         'int 0x80', see 'kernel::syscall'. User threads must not call   ║
+   ║         kernel functions directly, only the code of the kernel image    ║
+   ║         and their user stack are accessible in ring 3.                  ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::arch::asm;
+
+use crate::kernel::syscall::{SYSNO_EXIT, SYSNO_GET_TID, SYSNO_PRINT, SYSNO_SLEEP_MS, SYSNO_YIELD};
+
+// Systemaufruf 'number' mit bis zu drei Parametern
+fn syscall(number: u64, arg1: u64, arg2: u64, arg3: u64) -> u64 {
+    let result: u64;
+    unsafe {
+        asm!(
+            "int 0x80",
+            inlateout("rax") number => result,
+            in("rdi") arg1,
+            in("rsi") arg2,
+            in("rdx") arg3,
+        );
+    }
+    result
+}
+
+/**
+ Description: End the calling user thread with exit code `code`
+*/
+pub fn usr_exit(code: i32) -> ! {
+    syscall(SYSNO_EXIT, code as u64, 0, 0);
+    unreachable!();
+}
+
+/**
+ Description: Give the cpu to another thread
+*/
+pub fn usr_yield() {
+    syscall(SYSNO_YIELD, 0, 0, 0);
+}
+
+/**
+ Description: Block the calling thread for at least `ms` milliseconds
+*/
+pub fn usr_sleep_ms(ms: u64) {
+    syscall(SYSNO_SLEEP_MS, ms, 0, 0);
+}
+
+/**
+ Description: Print `s` on the screen. `s` must be accessible in ring 3,
+              e.g. a string literal or a string on the user stack.
+
+ Return: \
+    `false` if the kernel rejected the string
+*/
+pub fn usr_print(s: &str) -> bool {
+    syscall(SYSNO_PRINT, s.as_ptr() as u64, s.len() as u64, 0) == s.len() as u64
+}
+
+/**
+ Description: Get the tid of the calling thread
+*/
+pub fn usr_get_tid() -> usize {
+    syscall(SYSNO_GET_TID, 0, 0, 0) as usize
+}
@@ -33,6 +33,7 @@ mod user;
 
 
 use alloc::boxed::Box;
+use alloc::format;
 use boot::multiboot;
 use devices::cga::clear;
 use core::panic::PanicInfo;
@@ -48,10 +49,18 @@ use kernel::cpu;
 use kernel::interrupts;
 use kernel::interrupts::intdispatcher;
 use kernel::interrupts::intdispatcher::int_disp;
+use kernel::threads::cpu_load;
 use kernel::threads::idle_thread;
+use kernel::smp;
+use kernel::threads::reaper_thread;
 use kernel::threads::scheduler;
-use kernel::threads::thread::Thread;
+use kernel::threads::thread;
 use kernel::allocator;
+use kernel::frames;
+use kernel::pagefault;
+use kernel::paging;
+use kernel::syscall;
+use kernel::threads::stack;
 
 use user::aufgabe1::text_demo;
 use user::aufgabe1::keyboard_demo;
@@ -73,7 +82,7 @@ use user::aufgabe6::semaphore_demo;
 use user::aufgabe7;
 
 use devices::vga;
-use mylib::input::getch;
+use mylib::input::{getch, wait_for_return};
 
 use crate::boot::multiboot::PhysRegion;
 
@@ -157,6 +166,13 @@ fn show_menu(){
     println!("2. Sound abspielen");
     println!("3. Speicherverwaltung");
     println!("4. Preemptives Multitasking");
+    println!("5. Kooperative Threads");
+    println!("6. Preemptive Threads");
+    println!("7. Threads und CPU-Last anzeigen");
+    println!("8. Game of Life");
+    println!("9. Kill und Join");
+    println!("a. EDF-Zulassungstest");
+    println!("b. Kanaele schliessen");
 
 
         let input = getch();
@@ -167,11 +183,63 @@ fn show_menu(){
         } else if input == '3' as u8 {
             heap_demo::run();
         } else if input == '4' as u8 {
-            aufgabe6::semaphore_demo::init();
+            aufgabe6::semaphore_demo::run();
+        } else if input == '5' as u8 {
+            coop_thread_demo::run();
+        } else if input == '6' as u8 {
+            aufgabe5::preem_thread_demo::run();
+        } else if input == '7' as u8 {
+            cga::clear();
+            println!(
+                "Scheduling-Policy: {}, CPUs: {}, freie Frames: {} von {}",
+                scheduler::Scheduler::get_policy_name(),
+                smp::cpu_count(),
+                frames::free_frames(),
+                frames::total_frames()
+            );
+            scheduler::ps();
+            println!("{}", cpu_load::get_load());
+            if smp::cpu_count() > 1 {
+                for cpu in 0..smp::cpu_count() {
+                    println!("   CPU {}: {}", cpu, cpu_load::get_cpu_load(cpu));
+                }
+            }
+        } else if input == '8' as u8 {
+            aufgabe7::game_of_life::run();
+        } else if input == '9' as u8 {
+            aufgabe5::kill_join_demo::run();
+        } else if input == 'a' as u8 {
+            aufgabe5::edf_demo::run();
+        } else if input == 'b' as u8 {
+            aufgabe6::channel_demo::run();
         } else {
-            println!("ERR: Unbekannter input! System bitte neustarten");
+            println!("ERR: Unbekannter input!");
         }
 
+    // Die Demos warten auf das Ende ihrer Threads, danach geht es zurueck ins Menue
+    println!("Weiter mit Enter");
+    wait_for_return();
+}
+
+// Scheduling-Policy anhand der Kernel-Kommandozeile setzen, sonst bleibt es bei Round-Robin
+fn select_policy(mbi: u64) {
+    let cmdline = multiboot::get_cmdline(mbi).unwrap_or("");
+    kprintln!("   cmdline: {}", cmdline);
+    for arg in cmdline.split_whitespace() {
+        if let Some(name) = arg.strip_prefix("sched=") {
+            if scheduler::Scheduler::set_policy(name) == false {
+                println!("Unbekannte Scheduling-Policy '{}', nutze Round-Robin", name);
+            }
+        }
+    }
+    kprintln!("   scheduling policy: {}", scheduler::Scheduler::get_policy_name());
+}
+
+// Einstiegsfunktion des Menue-Threads
+extern "C" fn menu_thread_entry() {
+    loop {
+        show_menu();
+    }
 }
 
 #[no_mangle]
@@ -188,26 +256,73 @@ pub extern "C" fn kmain(mbi: u64) {
     // Multiboot-Infos ausgeben
     multiboot::dump(mbi);
 
+    // Der restliche freie Speicher wird in Frames verwaltet
+    let heap_region = PhysRegion {
+        start: heap_start as u64,
+        end: (heap_start + consts::HEAP_SIZE - 1) as u64,
+    };
+    let free_memory = multiboot::get_free_memory(mbi, kernel_region, heap_region);
+    frames::init(&free_memory);
+    kprintln!("   frames: {} free of {}", frames::free_frames(), frames::total_frames());
+
+    // NX-Bit fuer die Seitentabellen einschalten
+    paging::init();
+
+    // Virtuellen Bereich fuer die Thread-Stacks anlegen
+    stack::init();
+
     // Interrupt-Strukturen initialisieren
     interrupts::init();
 
+    // Seitenfehler auf einem eigenen Stack behandeln (Thread-Stacks wachsen dabei)
+    pagefault::init(0);
+
     // Tastatur-Unterbrechungsroutine 'einstoepseln'
     keyboard::Keyboard::plugin();
 
     // Zeitgeber-Unterbrechungsroutine 'einstoepseln'
     pit::plugin();
 
+    // Systemaufrufe der User-Threads ('int 0x80') erlauben
+    syscall::plugin();
+
+    // Weitere CPUs suchen und starten (warten bis zum Start des Schedulers)
+    smp::init();
+
     clear();
 
-    // Idle-Thread eintragen
-    let idle_thread = Thread::new(scheduler::next_thread_id(), idle_thread::idle_thread_entry, true);
-    scheduler::Scheduler::ready(idle_thread);
+    // Scheduling-Policy waehlen ('sched=rr' oder 'sched=fair' in 'grub.cfg')
+    select_policy(mbi);
+
+    // Idle-Thread je CPU eintragen (braucht nur einen kleinen Stack)
+    for cpu in 0..smp::cpu_count() {
+        let idle_thread = thread::Builder::new()
+            .name(&format!("idle-{}", cpu))
+            .priority(0)
+            .kernel_stack_size(0x4000)
+            .affinity(1 << cpu)
+            .build(|| idle_thread::idle_thread_entry());
+        scheduler::Scheduler::set_idle_thread(cpu, idle_thread);
+    }
+
+    // Reaper-Thread eintragen (gibt beendete Threads frei)
+    thread::Builder::new()
+        .name("reaper")
+        .priority(0)
+        .kernel_stack_size(0x8000)
+        .spawn(|| reaper_thread::reaper_thread_entry());
 
     // HelloWorld-Thread eintragen
-    let hello_world_thread = Thread::new(scheduler::next_thread_id(), hello_world_thread::hello_world_thread_entry, false);
+    let hello_world_thread = thread::Thread::new(scheduler::next_thread_id(), hello_world_thread::hello_world_thread_entry, false, consts::DEFAULT_PRIORITY);
     scheduler::Scheduler::ready(hello_world_thread);
 
-    // Scheduler starten & Interrupts erlauben
+    // Menue-Thread eintragen
+    thread::Builder::new()
+        .name("menu")
+        .spawn(|| menu_thread_entry());
+
+    // Scheduler auf den APs und dann hier starten & Interrupts erlauben
+    smp::start_scheduling();
     scheduler::Scheduler::schedule();
 }
 
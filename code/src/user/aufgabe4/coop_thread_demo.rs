@@ -1,4 +1,5 @@
 
+use crate::consts;
 use crate::kernel::threads::{scheduler::{self, Scheduler}, thread};
 
 use super::coop_thread_loop;
@@ -8,26 +9,38 @@ use super::coop_thread_loop;
 extern "C" fn coop_demo_thread_entry() {
 
    /* Hier muss Code eingefuegt werden */
-   coop_thread_loop::init();
-   coop_thread_loop::init();
-   coop_thread_loop::init();
+   let loop1 = coop_thread_loop::init();
+   let loop2 = coop_thread_loop::init();
+   let loop3 = coop_thread_loop::init();
 
    for _ in 0..10000 {
       scheduler::Scheduler::yield_cpu();
    }
+
+   // Loop-Threads beenden und auf deren Ende warten
+   coop_thread_loop::stop();
+   loop1.join();
+   loop2.join();
+   loop3.join();
    scheduler::Scheduler::exit();
 
 }
 
 
 /**
- Return thread id of created thread
+ Return handle of created thread
 */
-pub fn init() -> usize {
+pub fn init() -> thread::JoinHandle {
 
    /* Hier muss Code eingefuegt werden */
    let tid = scheduler::next_thread_id();
-   let coop_demo_thread = thread::Thread::new(tid, coop_demo_thread_entry, false);
-   scheduler::Scheduler::ready(coop_demo_thread);
-   return tid as usize;
+   let coop_demo_thread = thread::Thread::new(tid, coop_demo_thread_entry, true, consts::DEFAULT_PRIORITY);
+   return scheduler::Scheduler::ready(coop_demo_thread);
+}
+
+/**
+ Description: Start the demo and wait until it has finished
+*/
+pub fn run() {
+   init().join();
 }
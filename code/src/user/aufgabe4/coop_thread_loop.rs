@@ -1,14 +1,20 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::consts;
 use crate::devices::cga;
 use crate::kernel::threads::thread;
 use crate::kernel::threads::scheduler;
 use crate::kernel::threads::thread::Thread;
 
+// Die Loop-Threads laufen, bis 'stop' gerufen wird
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
 #[no_mangle]
 extern "C" fn coop_thread_loop_entry() {
 
    /* Hier muss Code eingefuegt werden */
    let mut counter = 0;
-   loop {
+   while RUNNING.load(Ordering::SeqCst) {
       cga::setpos(17 * scheduler::get_active_tid() as u64 - 10, 20);
       println!("Loop [{}] : {}  ",scheduler::get_active_tid() as u64, counter);
       counter += 1;
@@ -17,12 +23,19 @@ extern "C" fn coop_thread_loop_entry() {
 
 }
 
-pub fn init() {
+pub fn init() -> thread::JoinHandle {
 
    /* Hier muss Code eingefuegt werden */
+   RUNNING.store(true, Ordering::SeqCst);
    let tid = scheduler::next_thread_id();
-   let coop_thread_loop = thread::Thread::new(tid, coop_thread_loop_entry, false);
-   scheduler::Scheduler::ready(coop_thread_loop);
-   tid as usize;
+   let coop_thread_loop = thread::Thread::new(tid, coop_thread_loop_entry, true, consts::DEFAULT_PRIORITY);
+   scheduler::Scheduler::ready(coop_thread_loop)
+
+}
 
+/**
+ Description: All loop threads leave their loop and terminate
+*/
+pub fn stop() {
+   RUNNING.store(false, Ordering::SeqCst);
 }
@@ -1,10 +1,8 @@
-use crate::kernel::cpu;
-use crate::kernel::threads::scheduler;
+use crate::mylib::usrlib;
 
+// Laeuft im Ring 3, Ausgaben daher nur ueber Systemaufrufe
 pub extern "C" fn hello_world_thread_entry() {
-    //    let tid = scheduler::get_active_tid();
-    //    println!("Hello World! thread-id = {}", tid);
-    // kprintln!("Hello World");
+    usrlib::usr_print("Hello World from a user thread!\n");
     //    let val = cpu::inb(1);
     loop {
         //print!("U");
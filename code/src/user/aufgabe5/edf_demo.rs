@@ -0,0 +1,66 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::consts;
+use crate::devices::cga;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::thread::{self, JoinHandle};
+
+// Perioden je Echtzeit-Thread, danach beendet er sich
+const PERIODS: u64 = 50;
+
+// Abgeschlossene Perioden aller Echtzeit-Threads
+static JOBS: AtomicU64 = AtomicU64::new(0);
+
+fn realtime_entry() {
+    for _ in 0..PERIODS {
+        JOBS.fetch_add(1, Ordering::SeqCst);
+        Scheduler::wait_next_period();
+    }
+}
+
+// Echtzeit-Thread mit Periode und Deadline 10 Ticks und Budget 'budget' anlegen
+fn spawn(name: &str, budget: u64) -> Option<JoinHandle> {
+    let handle = thread::Builder::new()
+        .name(name)
+        .realtime(10, budget, 10)
+        .spawn_realtime(realtime_entry);
+    println!(
+        "{}: Budget {} von 10 Ticks ({}%) -> {}",
+        name,
+        budget,
+        budget * 10,
+        if handle.is_some() { "zugelassen" } else { "abgelehnt" }
+    );
+    handle
+}
+
+/**
+ Description: Show the admission control of the EDF scheduler: real-time
+              threads are only admitted while the sum of their utilizations
+              stays below `RT_MAX_UTILIZATION`. A rejected thread is admitted
+              after another one has ended.
+*/
+pub fn run() {
+    cga::clear();
+    println!("EDF-Zulassungstest, maximale Auslastung {}%", consts::RT_MAX_UTILIZATION);
+    println!("");
+    JOBS.store(0, Ordering::SeqCst);
+
+    let a = spawn("rt-a", 4);
+    let b = spawn("rt-b", 4);
+    // 40% + 40% + 20% ueberschreitet die Grenze, 'rt-c' wird abgelehnt
+    let rejected = spawn("rt-c", 2);
+
+    // Nach dem Ende von 'rt-a' ist wieder Platz fuer 'rt-c'
+    if let Some(a) = a {
+        a.join();
+    }
+    println!("rt-a beendet");
+    let c = spawn("rt-c", 2);
+
+    for handle in [b, rejected, c].iter().flatten() {
+        handle.join();
+    }
+    println!("");
+    println!("Abgeschlossene Perioden: {} (erwartet {})", JOBS.load(Ordering::SeqCst), 3 * PERIODS);
+}
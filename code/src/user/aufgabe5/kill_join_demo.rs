@@ -0,0 +1,47 @@
+use crate::devices::cga;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::thread::{self, EXIT_CODE_KILLED};
+use crate::mylib::mutex::Mutex;
+
+static LOCK: Mutex<u32> = Mutex::new(0);
+
+/**
+ Description: Show `JoinHandle::join` and `Scheduler::kill`: a thread ending
+              with its own exit code, a killed sleeping thread and a killed
+              thread blocked in a mutex. Waits until all threads have ended.
+*/
+pub fn run() {
+    cga::clear();
+    println!("Kill und Join (Exit-Code getoeteter Threads: {})", EXIT_CODE_KILLED);
+    println!("");
+
+    // Der Thread beendet sich selbst, 'join' liefert seinen Exit-Code
+    let worker = thread::Builder::new().name("worker").spawn(|| {
+        Scheduler::sleep_ms(100);
+        Scheduler::exit_with_code(42);
+    });
+    let code = worker.join();
+    println!("worker  (tid {}): Exit-Code {} (erwartet 42)", worker.get_tid(), code);
+
+    // Ein schlafender Thread wird aus der Sleep-Queue geholt
+    let sleeper = thread::Builder::new()
+        .name("sleeper")
+        .spawn(|| Scheduler::sleep_ms(60_000));
+    Scheduler::sleep_ms(50);
+    let killed = Scheduler::kill(sleeper.get_tid());
+    let code = sleeper.join();
+    println!("sleeper (tid {}): getoetet = {}, Exit-Code {}", sleeper.get_tid(), killed, code);
+
+    // Ein in der Mutex blockierter Thread wird aus deren Warteschlange geholt,
+    // die Mutex bleibt danach benutzbar
+    let guard = LOCK.lock();
+    let blocked = thread::Builder::new().name("blocked").spawn(|| {
+        *LOCK.lock() += 1;
+    });
+    Scheduler::sleep_ms(50);
+    let killed = Scheduler::kill(blocked.get_tid());
+    drop(guard);
+    let code = blocked.join();
+    println!("blocked (tid {}): getoetet = {}, Exit-Code {}", blocked.get_tid(), killed, code);
+    println!("Mutex frei: {}, Wert {} (erwartet 0)", LOCK.get_owner().is_none(), *LOCK.lock());
+}
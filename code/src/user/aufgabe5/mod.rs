@@ -1 +1,3 @@
+pub mod edf_demo;
+pub mod kill_join_demo;
 pub mod preem_thread_demo;
\ No newline at end of file
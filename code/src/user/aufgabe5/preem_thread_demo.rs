@@ -1,15 +1,21 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::consts;
 use crate::devices::cga;
 use crate::devices::pcspk;
 use crate::kernel::threads::thread;
 use crate::kernel::threads::scheduler;
 use crate::kernel::threads::thread::Thread;
 
+// Der Loop-Thread laeuft, bis die Musik zu Ende ist
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
 #[no_mangle]
 extern "C" fn thread_loop_entry() {
 
    /* Hier muss Code eingefuegt werden */
    let mut counter = 0;
-   loop {
+   while RUNNING.load(Ordering::SeqCst) {
       cga::setpos(17 * scheduler::get_active_tid() as u64 - 10, 20);
       println!("Loop [{}] : {}  ",scheduler::get_active_tid() as u64, counter);
       counter += 1;
@@ -22,14 +28,27 @@ extern "C" fn thread_tetris() {
    pcspk::tetris();
 }
 
-pub fn init() {
+pub fn init() -> (thread::JoinHandle, thread::JoinHandle) {
 
    /* Hier muss Code eingefuegt werden */
+   RUNNING.store(true, Ordering::SeqCst);
    let tid = scheduler::next_thread_id();
-   let thread_loop = thread::Thread::new(tid, thread_loop_entry, false);
-   scheduler::Scheduler::ready(thread_loop);
+   let thread_loop = thread::Thread::new(tid, thread_loop_entry, true, consts::DEFAULT_PRIORITY);
+   let loop_handle = scheduler::Scheduler::ready(thread_loop);
    
    let tid2 = scheduler::next_thread_id();
-   let thread_loop2 = thread::Thread::new(tid2, thread_tetris, false);
-   scheduler::Scheduler::ready(thread_loop2);
-}
\ No newline at end of file
+   let thread_loop2 = thread::Thread::new(tid2, thread_tetris, true, consts::DEFAULT_PRIORITY);
+   let tetris_handle = scheduler::Scheduler::ready(thread_loop2);
+
+   (loop_handle, tetris_handle)
+}
+
+/**
+ Description: Start the demo and wait until the music has finished
+*/
+pub fn run() {
+   let (loop_handle, tetris_handle) = init();
+   tetris_handle.join();
+   RUNNING.store(false, Ordering::SeqCst);
+   loop_handle.join();
+}
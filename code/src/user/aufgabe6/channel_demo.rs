@@ -0,0 +1,47 @@
+use crate::devices::cga;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::thread;
+use crate::mylib::channel::{self, RecvError, SendError};
+
+/**
+ Description: Show the closing of channels: after the sender is gone the
+              receiver still gets the buffered messages and then `RecvError`,
+              after the receiver is gone a blocked sender gets its message
+              back as `SendError`. Waits until all threads have ended.
+*/
+pub fn run() {
+    cga::clear();
+    println!("Kanaele schliessen");
+    println!("");
+
+    // Der Sender schickt 5 Nachrichten und endet, bevor eine empfangen wird
+    let (tx, rx) = channel::bounded::<u32>(5);
+    let producer = thread::Builder::new().name("producer").spawn(move || {
+        for i in 1..=5 {
+            tx.send(i).unwrap();
+        }
+    });
+    producer.join();
+    loop {
+        match rx.recv() {
+            Ok(msg) => println!("empfangen: {}", msg),
+            Err(RecvError) => {
+                println!("Sender weg, recv liefert RecvError");
+                break;
+            }
+        }
+    }
+
+    // Der Sender blockiert im vollen Kanal, bis der Empfaenger verschwindet
+    let (tx, rx) = channel::bounded::<u32>(1);
+    let blocked = thread::Builder::new().name("blocked-sender").spawn(move || {
+        tx.send(1).unwrap();
+        match tx.send(2) {
+            Ok(()) => println!("send: unerwartet zugestellt"),
+            Err(SendError(msg)) => println!("Empfaenger weg, send liefert {} zurueck", msg),
+        }
+    });
+    Scheduler::sleep_ms(100);
+    drop(rx);
+    blocked.join();
+}
@@ -1,2 +1,2 @@
-
-pub mod semaphore_demo;
+pub mod channel_demo;
+pub mod semaphore_demo;
\ No newline at end of file
@@ -1,5 +1,8 @@
+use alloc::format;
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::mutex;
 
+use crate::consts;
 use crate::kernel::threads::thread;
 use crate::kernel::threads::scheduler;
 use crate::cga;
@@ -11,24 +14,25 @@ use crate::mylib::spinlock;
 
 //static spinlock
 static LOCK: spinlock::Spinlock<i32> = spinlock::Spinlock::new(0);
-static MUTEX: Mutex = Mutex::new();
+static MUTEX: Mutex<()> = Mutex::new(());
 
-#[no_mangle]
-extern "C" fn synced_loop_thread_entry() {
+// Die Loop-Threads laufen, bis die Musik zu Ende ist
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn synced_loop_thread_entry(idx: u64) {
     
    /* Hier muss Code eingefuegt werden */
    let mut cnt = 0;
-    let my_tid = scheduler::get_active_tid() as u64;
-    loop {
+    while RUNNING.load(Ordering::SeqCst) {
 
         /* Hier muss Code eingefuegt werden */
         //let enabled = cpu::disable_int_nested();
         
         {
             let m = MUTEX.lock();
-            cga::setpos(5 + (my_tid-1) * 20, 10);
+            cga::setpos(5 + idx * 20, 10);
             delay::delay(10);
-            println!("Loop [{}] : {}", my_tid, cnt);
+            println!("Loop [{}] : {}", idx, cnt);
             //cpu::enable_int_nested(enabled);
     
         }
@@ -43,17 +47,32 @@ extern "C" fn music(){
     pcspk::aerodynamic();
 }
 
-pub fn init() {
+pub fn init() -> [thread::JoinHandle; 4] {
 
    /* Hier muss Code eingefuegt werden */
-    let thread1 = thread::Thread::new(scheduler::next_thread_id(), synced_loop_thread_entry, false);
-    let thread2 = thread::Thread::new(scheduler::next_thread_id(), synced_loop_thread_entry, false);
-    let thread3 = thread::Thread::new(scheduler::next_thread_id(), synced_loop_thread_entry, false);
-    let thread4 = thread::Thread::new(scheduler::next_thread_id(), music, false);
+    RUNNING.store(true, Ordering::SeqCst);
+    let [loop1, loop2, loop3] = [0, 1, 2].map(|idx| {
+        thread::Builder::new()
+            .name(&format!("loop-{}", idx))
+            .kernel_stack_size(0x10000)
+            .spawn(move || synced_loop_thread_entry(idx))
+    });
+    let music_thread = thread::Builder::new()
+        .name("music")
+        .priority(consts::PRIORITY_LEVELS - 1)
+        .spawn(|| music());
 
-    scheduler::Scheduler::ready(thread1);
-    scheduler::Scheduler::ready(thread2);
-    scheduler::Scheduler::ready(thread3);
-    scheduler::Scheduler::ready(thread4);
+    [loop1, loop2, loop3, music_thread]
+}
 
+/**
+ Description: Start the demo and wait until the music has finished
+*/
+pub fn run() {
+    let [loop1, loop2, loop3, music_thread] = init();
+    music_thread.join();
+    RUNNING.store(false, Ordering::SeqCst);
+    loop1.join();
+    loop2.join();
+    loop3.join();
 }
@@ -11,7 +11,6 @@ use crate::devices::pit::get_systime;
 use crate::devices::vga;
 use crate::kernel::threads::scheduler;
 use crate::kernel::threads::thread;
-use crate::mylib::input::getch;
 
 #[derive(Copy, Clone)]
 enum BlockSize {
@@ -47,7 +46,7 @@ impl Board{
             }
         }
 
-        vga::draw_string(0, yres-10, vga::rgb_24(0, 0, 255), "(1) Toggle Draw (WASD), (3) Start/Stop, (4) Set Blocksize, (Enter) Next Generation");
+        vga::draw_string(0, yres-10, vga::rgb_24(0, 0, 255), "(1) Toggle Draw (WASD), (3) Start/Stop, (4) Set Blocksize, (Enter) Next Generation, (Q) Quit");
     }
 
     pub fn change_board_size(&mut self, size: BlockSize) {
@@ -130,7 +129,7 @@ impl Board{
                     vga::draw_pixel(courser_pos.0 + i, courser_pos.1 + j, vga::rgb_24(255, 0, 0));
                 }
             }
-            let key = getch();
+            let key = wait_key();
             //if W pressed
             if key == 119{
                 if courser_pos.1 == 0{
@@ -181,6 +180,18 @@ impl Board{
     }
 }
 
+// Auf eine Taste warten, ohne das Budget zu verbrauchen: als Echtzeit-Thread
+// wird je Periode einmal nachgesehen, dazwischen laeuft 'wait_next_period'
+fn wait_key() -> u8 {
+    loop {
+        let key = get_lastkey();
+        if key != 0 {
+            return key;
+        }
+        scheduler::Scheduler::wait_next_period();
+    }
+}
+
 /**
  Description: Entry function of the graphic demo thread
 */
@@ -231,37 +242,35 @@ extern "C" fn game_run() {
 
     board.draw_board();
 
+    // Jeder Durchlauf endet mit 'wait_next_period', auch wenn das Spiel steht
+    let mut play = false;
     loop {
-        let key = getch();
+        let key = get_lastkey();
         //check if key is enter
-        if key == 13 {
+        if key == 13 && play == false {
             board.update_field();
             board.draw_board();
         }
         //check if key is 1 for Draw
-        if key == 49{
+        if key == 49 && play == false {
             board.draw_courser();
             board.draw_board();
         }
 
         //check if key is 3 for Start/Stop
-        let mut play = false;
         if key == 51{
             play = !play;
-            while play{
-                board.update_field();
-                board.draw_board();
-                let key = get_lastkey();
-                if key == 51{
-                    play = !play
-                }
-            }
+        }
+
+        //check if key is q for Quit
+        if key == 113{
+            break;
         }
 
         //check if key is 4
-        if key == 52{
+        if key == 52 && play == false {
             vga::draw_string(xres/4, yres/2, vga::rgb_24(0, 0, 0), "Small (1), Medium (2), Large (3)");
-            let key = getch();
+            let key = wait_key();
             if key == 49{
                 board.change_board_size(BlockSize::Small);
             }
@@ -273,15 +282,43 @@ extern "C" fn game_run() {
             }
             board.draw_board();
         }
+
+        // eine Generation je Periode
+        if play {
+            board.update_field();
+            board.draw_board();
+        }
+        scheduler::Scheduler::wait_next_period();
     }
 
 }
 
 
+// Periode, Budget und Deadline des Spiel-Threads in Ticks (100ms je Generation)
+const GAME_PERIOD: u64 = 10;
+const GAME_BUDGET: u64 = 6;
+
+/**
+ Description: Create and add the game thread as real-time (kernel) thread
+
+ Return: \
+    `None` if the admission control rejected the thread
+*/
+pub fn init() -> Option<thread::JoinHandle> {
+    thread::Builder::new()
+        .name("game_of_life")
+        .realtime(GAME_PERIOD, GAME_BUDGET, GAME_PERIOD)
+        .spawn_realtime(|| game_run())
+}
+
 /**
- Description: Create and add the graphic demo thread
+ Description: Start the game and wait until it is quit
 */
-pub fn init() { 
-    let game_thread = thread::Thread::new(scheduler::next_thread_id(), game_run,false);
-    scheduler::Scheduler::ready(game_thread);
+pub fn run() {
+    match init() {
+        Some(game) => {
+            game.join();
+        }
+        None => println!("Game of Life: zu wenig Rechenzeit fuer Echtzeit-Thread"),
+    }
 }
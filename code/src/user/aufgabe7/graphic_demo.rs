@@ -1,5 +1,6 @@
 use alloc::boxed::Box;
 
+use crate::consts;
 use crate::devices::cga; 
 use crate::devices::cga_print; 
 use crate::devices::fonts::font_8x8;
@@ -85,7 +86,7 @@ extern "C" fn graphic_thread_entry() {
 /**
  Description: Create and add the graphic demo thread
 */
-pub fn init() {
-    let graphic_thread = thread::Thread::new(scheduler::next_thread_id(), graphic_thread_entry, false);
-    scheduler::Scheduler::ready(graphic_thread);
+pub fn init() -> thread::JoinHandle {
+    let graphic_thread = thread::Thread::new(scheduler::next_thread_id(), graphic_thread_entry, true, consts::DEFAULT_PRIORITY);
+    scheduler::Scheduler::ready(graphic_thread)
 }